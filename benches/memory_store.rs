@@ -0,0 +1,62 @@
+//! Compares reading all documents of an in-memory store
+//! that keeps JSON text with one that keeps parsed values.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use jfs::{Config, Store, IN_MEMORY};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
+struct Doc {
+    name: String,
+    tags: Vec<String>,
+    score: f64,
+    nested: Nested,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Nested {
+    a: u64,
+    b: Option<String>,
+}
+
+fn store(parsed: bool, count: u32) -> Store {
+    let cfg = Config {
+        parsed,
+        ..Config::default()
+    };
+    let store = Store::new_with_cfg(IN_MEMORY, cfg).unwrap();
+    for i in 0..count {
+        let doc = Doc {
+            name: format!("document {i}"),
+            tags: (0..10).map(|t| format!("tag-{t}")).collect(),
+            score: f64::from(i) / 3.0,
+            nested: Nested {
+                a: u64::from(i),
+                b: Some("x".repeat(100)),
+            },
+        };
+        store.save_with_id(&doc, &i.to_string()).unwrap();
+    }
+    store
+}
+
+fn all(c: &mut Criterion) {
+    let mut group = c.benchmark_group("all");
+    for (name, parsed) in [("text", false), ("parsed", true)] {
+        let store = store(parsed, 10_000);
+        group.bench_function(name, |b| b.iter(|| store.all::<Doc>().unwrap()));
+    }
+    group.finish();
+}
+
+fn get(c: &mut Criterion) {
+    let mut group = c.benchmark_group("get");
+    for (name, parsed) in [("text", false), ("parsed", true)] {
+        let store = store(parsed, 1_000);
+        group.bench_function(name, |b| b.iter(|| store.get::<Doc>("500").unwrap()));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, all, get);
+criterion_main!(benches);
@@ -0,0 +1,578 @@
+use std::{
+    collections::BTreeMap,
+    io::{Error, ErrorKind, Result},
+    ops::RangeBounds,
+    path::Path,
+    sync::Arc,
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    json_store::Decoded, Autosave, CacheStats, Clock, Config, JsonPatch, Metadata, Page,
+    RepairPolicy, Report, ScanOptions, Store, Sweeper, SystemClock, TrashEntry, Version,
+};
+
+/// An asynchronous wrapper around [`Store`] for use with [tokio](https://tokio.rs).
+///
+/// All blocking file I/O and locking is moved to tokio's blocking
+/// thread pool (see [`tokio::task::spawn_blocking`]), so the async
+/// executor is never blocked.
+/// Like `Store`, an `AsyncStore` is cheap to clone and all clones
+/// share the same underlying store.
+///
+/// This type is only available if the `tokio` feature is enabled.
+#[derive(Debug, Clone)]
+pub struct AsyncStore {
+    store: Store,
+}
+
+impl AsyncStore {
+    /// Opens an `AsyncStore` against the specified path.
+    ///
+    /// See `Store::new(..)` for more details.
+    pub async fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::new_with_cfg(path, Config::default()).await
+    }
+
+    /// Opens an `AsyncStore` against the specified path with the given configuration.
+    ///
+    /// See `Store::new_with_cfg(..)` for more details.
+    pub async fn new_with_cfg<P: AsRef<Path>>(path: P, cfg: Config) -> Result<Self> {
+        Self::new_with_clock(path, cfg, Arc::new(SystemClock)).await
+    }
+
+    /// Opens an `AsyncStore` like `new_with_cfg(..)` that takes all timestamps
+    /// from the given `clock`.
+    ///
+    /// See `Store::new_with_clock(..)` for more details.
+    pub async fn new_with_clock<P: AsRef<Path>>(
+        path: P,
+        cfg: Config,
+        clock: Arc<dyn Clock>,
+    ) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let store = spawn_blocking(move || Store::new_with_clock(path, cfg, clock)).await?;
+        Ok(Self::from(store))
+    }
+
+    /// See `Store::load_into_memory`.
+    pub async fn load_into_memory<P: AsRef<Path>>(path: P, cfg: Config) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let store = spawn_blocking(move || Store::load_into_memory(path, cfg)).await?;
+        Ok(Self::from(store))
+    }
+
+    /// Returns the storage path for the backing JSON store.
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        self.store.path()
+    }
+
+    /// Returns the underlying blocking `Store`.
+    #[must_use]
+    pub const fn blocking(&self) -> &Store {
+        &self.store
+    }
+
+    pub async fn save<T>(&self, obj: &T) -> Result<String>
+    where
+        T: Serialize,
+    {
+        let json = to_value(obj)?;
+        self.run(move |s| s.save(&json)).await
+    }
+
+    pub async fn save_with_id<T>(&self, obj: &T, id: &str) -> Result<String>
+    where
+        T: Serialize,
+    {
+        let json = to_value(obj)?;
+        let id = id.to_owned();
+        self.run(move |s| s.save_with_id(&json, &id)).await
+    }
+
+    /// See `Store::save_many`.
+    pub async fn save_many<T>(&self, docs: &[(&str, T)]) -> Result<Vec<Result<String>>>
+    where
+        T: Serialize,
+    {
+        let mut ids = vec![];
+        let mut values = vec![];
+        for (id, obj) in docs {
+            ids.push((*id).to_owned());
+            values.push(to_value(obj)?);
+        }
+        self.run(move |s| {
+            let docs = ids
+                .iter()
+                .map(String::as_str)
+                .zip(values)
+                .collect::<Vec<_>>();
+            s.save_many(&docs)
+        })
+        .await
+    }
+
+    /// See `Store::save_value`.
+    pub async fn save_value(&self, doc: &Value, id: &str) -> Result<String> {
+        let doc = doc.clone();
+        let id = id.to_owned();
+        self.run(move |s| s.save_value(&doc, &id)).await
+    }
+
+    /// See `Store::save_with_ttl`.
+    pub async fn save_with_ttl<T>(&self, obj: &T, id: &str, ttl: Duration) -> Result<String>
+    where
+        T: Serialize,
+    {
+        let json = to_value(obj)?;
+        let id = id.to_owned();
+        self.run(move |s| s.save_with_ttl(&json, &id, ttl)).await
+    }
+
+    pub async fn get<T>(&self, id: &str) -> Result<T>
+    where
+        for<'de> T: Deserialize<'de> + Send + 'static,
+    {
+        let id = id.to_owned();
+        self.run(move |s| s.get(&id)).await
+    }
+
+    /// See `Store::get_value`.
+    pub async fn get_value(&self, id: &str) -> Result<Value> {
+        let id = id.to_owned();
+        self.run(move |s| s.get_value(&id)).await
+    }
+
+    /// See `Store::get_many`.
+    pub async fn get_many<T>(&self, ids: &[&str]) -> Result<Vec<Result<T>>>
+    where
+        for<'de> T: Deserialize<'de> + Send + 'static,
+    {
+        let ids = to_owned_ids(ids);
+        self.run(move |s| s.get_many(&ids.iter().map(String::as_str).collect::<Vec<_>>()))
+            .await
+    }
+
+    /// See `Store::exists`.
+    pub async fn exists(&self, id: &str) -> Result<bool> {
+        let id = id.to_owned();
+        self.run(move |s| s.exists(&id)).await
+    }
+
+    /// See `Store::keys`.
+    pub async fn keys(&self) -> Result<Vec<String>> {
+        self.run(Store::keys).await
+    }
+
+    /// See `Store::count`.
+    pub async fn count(&self) -> Result<usize> {
+        self.run(Store::count).await
+    }
+
+    /// See `Store::is_empty`.
+    pub async fn is_empty(&self) -> Result<bool> {
+        self.run(Store::is_empty).await
+    }
+
+    /// See `Store::scan_prefix`.
+    pub async fn scan_prefix<T>(&self, prefix: &str, opts: ScanOptions) -> Result<Vec<(String, T)>>
+    where
+        for<'de> T: Deserialize<'de> + Send + 'static,
+    {
+        let prefix = prefix.to_owned();
+        self.run(move |s| s.scan_prefix(&prefix, opts)).await
+    }
+
+    /// See `Store::range`.
+    pub async fn range<'a, T>(
+        &self,
+        range: impl RangeBounds<&'a str>,
+        opts: ScanOptions,
+    ) -> Result<Vec<(String, T)>>
+    where
+        for<'de> T: Deserialize<'de> + Send + 'static,
+    {
+        let start = range.start_bound().map(|id| (*id).to_owned());
+        let end = range.end_bound().map(|id| (*id).to_owned());
+        self.run(move |s| {
+            let range = (
+                start.as_ref().map(String::as_str),
+                end.as_ref().map(String::as_str),
+            );
+            s.range(range, opts)
+        })
+        .await
+    }
+
+    /// See `Store::page`.
+    pub async fn page<T>(&self, after: Option<&str>, limit: usize) -> Result<Page<T>>
+    where
+        for<'de> T: Deserialize<'de> + Send + 'static,
+    {
+        let after = after.map(str::to_owned);
+        self.run(move |s| s.page(after.as_deref(), limit)).await
+    }
+
+    /// See `Store::get_path`.
+    pub async fn get_path<T>(&self, id: &str, pointer: &str) -> Result<T>
+    where
+        for<'de> T: Deserialize<'de> + Send + 'static,
+    {
+        let id = id.to_owned();
+        let pointer = pointer.to_owned();
+        self.run(move |s| s.get_path(&id, &pointer)).await
+    }
+
+    /// See `Store::set_path`.
+    pub async fn set_path<V>(&self, id: &str, pointer: &str, value: &V) -> Result<()>
+    where
+        V: Serialize,
+    {
+        let value = to_value(value)?;
+        let id = id.to_owned();
+        let pointer = pointer.to_owned();
+        self.run(move |s| s.set_path(&id, &pointer, &value)).await
+    }
+
+    /// See `Store::patch`.
+    pub async fn patch<P>(&self, id: &str, merge_patch: &P) -> Result<()>
+    where
+        P: Serialize,
+    {
+        let merge_patch = to_value(merge_patch)?;
+        let id = id.to_owned();
+        self.run(move |s| s.patch(&id, &merge_patch)).await
+    }
+
+    /// See `Store::apply_patch`.
+    pub async fn apply_patch(&self, id: &str, patch: &JsonPatch) -> Result<()> {
+        let patch = patch.clone();
+        let id = id.to_owned();
+        self.run(move |s| s.apply_patch(&id, &patch)).await
+    }
+
+    /// See `Store::set_schema`.
+    #[cfg(feature = "jsonschema")]
+    pub fn set_schema(&self, schema: Option<&Value>) -> Result<()> {
+        self.store.set_schema(schema)
+    }
+
+    /// See `Store::validate_all`.
+    #[cfg(feature = "jsonschema")]
+    pub async fn validate_all(&self) -> Result<BTreeMap<String, crate::ValidationError>> {
+        self.run(Store::validate_all).await
+    }
+
+    /// See `Store::flush`.
+    pub async fn flush(&self) -> Result<()> {
+        self.run(Store::flush).await
+    }
+
+    /// See `Store::persist_to`.
+    pub async fn persist_to<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref().to_path_buf();
+        self.run(move |s| s.persist_to(path)).await
+    }
+
+    /// See `Store::convert`.
+    pub async fn convert<P: AsRef<Path>>(&self, to_path: P, cfg: Config) -> Result<Self> {
+        let to_path = to_path.as_ref().to_path_buf();
+        let store = self.run(move |s| s.convert(to_path, cfg)).await?;
+        Ok(Self::from(store))
+    }
+
+    /// See `Store::autosave`.
+    #[must_use]
+    pub fn autosave<P: AsRef<Path>>(&self, path: P, interval: Option<Duration>) -> Autosave {
+        self.store.autosave(path, interval)
+    }
+
+    /// See `Store::cache_stats`.
+    #[must_use]
+    pub fn cache_stats(&self) -> Option<CacheStats> {
+        self.store.cache_stats()
+    }
+
+    /// See `Store::on_evict`.
+    pub fn on_evict<F>(&self, callback: F)
+    where
+        F: Fn(&str, Value) + Send + Sync + 'static,
+    {
+        self.store.on_evict(callback);
+    }
+
+    /// See `Store::meta`.
+    pub async fn meta(&self, id: &str) -> Result<Metadata> {
+        let id = id.to_owned();
+        self.run(move |s| s.meta(&id)).await
+    }
+
+    /// See `Store::history`.
+    pub async fn history(&self, id: &str) -> Result<Vec<Version>> {
+        let id = id.to_owned();
+        self.run(move |s| s.history(&id)).await
+    }
+
+    /// See `Store::get_at`.
+    pub async fn get_at<T>(&self, id: &str, version: u64) -> Result<T>
+    where
+        for<'de> T: Deserialize<'de> + Send + 'static,
+    {
+        let id = id.to_owned();
+        self.run(move |s| s.get_at(&id, version)).await
+    }
+
+    /// See `Store::revert`.
+    pub async fn revert(&self, id: &str, version: u64) -> Result<()> {
+        let id = id.to_owned();
+        self.run(move |s| s.revert(&id, version)).await
+    }
+
+    pub async fn all<T>(&self) -> Result<BTreeMap<String, T>>
+    where
+        for<'de> T: Deserialize<'de> + Send + 'static,
+    {
+        self.run(Store::all).await
+    }
+
+    /// See `Store::all_values`.
+    pub async fn all_values(&self) -> Result<BTreeMap<String, Value>> {
+        self.run(Store::all_values).await
+    }
+
+    /// See `Store::all_strict`.
+    pub async fn all_strict<T>(&self) -> Result<BTreeMap<String, T>>
+    where
+        for<'de> T: Deserialize<'de> + Send + 'static,
+    {
+        self.run(Store::all_strict).await
+    }
+
+    /// See `Store::all_with_errors`.
+    pub async fn all_with_errors<T>(&self) -> Result<Decoded<T>>
+    where
+        for<'de> T: Deserialize<'de> + Send + 'static,
+    {
+        self.run(Store::all_with_errors).await
+    }
+
+    pub async fn delete(&self, id: &str) -> Result<()> {
+        let id = id.to_owned();
+        self.run(move |s| s.delete(&id)).await
+    }
+
+    /// See `Store::delete_many`.
+    pub async fn delete_many(&self, ids: &[&str]) -> Result<Vec<Result<()>>> {
+        let ids = to_owned_ids(ids);
+        self.run(move |s| s.delete_many(&ids.iter().map(String::as_str).collect::<Vec<_>>()))
+            .await
+    }
+
+    /// See `Store::clear`.
+    pub async fn clear(&self) -> Result<usize> {
+        self.run(Store::clear).await
+    }
+
+    /// See `Store::restore`.
+    pub async fn restore(&self, id: &str) -> Result<()> {
+        let id = id.to_owned();
+        self.run(move |s| s.restore(&id)).await
+    }
+
+    /// See `Store::list_trash`.
+    pub async fn list_trash(&self) -> Result<Vec<TrashEntry>> {
+        self.run(Store::list_trash).await
+    }
+
+    /// See `Store::empty_trash`.
+    pub async fn empty_trash(&self, older_than: Duration) -> Result<usize> {
+        self.run(move |s| s.empty_trash(older_than)).await
+    }
+
+    /// See `Store::purge_expired`.
+    pub async fn purge_expired(&self) -> Result<usize> {
+        self.run(Store::purge_expired).await
+    }
+
+    /// See `Store::spawn_sweeper`.
+    ///
+    /// The sweeper runs on its own thread, not on the tokio runtime.
+    #[must_use]
+    pub fn spawn_sweeper(&self, interval: Duration) -> Sweeper {
+        self.store.spawn_sweeper(interval)
+    }
+
+    /// See `Store::verify`.
+    pub async fn verify<T>(&self) -> Result<Report>
+    where
+        for<'de> T: Deserialize<'de> + 'static,
+    {
+        self.run(Store::verify::<T>).await
+    }
+
+    /// See `Store::repair`.
+    pub async fn repair<T>(&self, policy: RepairPolicy) -> Result<Report>
+    where
+        for<'de> T: Deserialize<'de> + 'static,
+    {
+        self.run(move |s| s.repair::<T>(policy)).await
+    }
+
+    async fn run<F, R>(&self, f: F) -> Result<R>
+    where
+        F: FnOnce(&Store) -> Result<R> + Send + 'static,
+        R: Send + 'static,
+    {
+        let store = self.store.clone();
+        spawn_blocking(move || f(&store)).await
+    }
+}
+
+impl From<Store> for AsyncStore {
+    fn from(store: Store) -> Self {
+        Self { store }
+    }
+}
+
+async fn spawn_blocking<F, R>(f: F) -> Result<R>
+where
+    F: FnOnce() -> Result<R> + Send + 'static,
+    R: Send + 'static,
+{
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|err| Error::new(ErrorKind::Other, err))?
+}
+
+fn to_owned_ids(ids: &[&str]) -> Vec<String> {
+    ids.iter().map(|id| (*id).to_owned()).collect()
+}
+
+fn to_value<T: Serialize>(obj: &T) -> Result<Value> {
+    serde_json::to_value(obj).map_err(|err| Error::new(ErrorKind::Other, err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::IN_MEMORY;
+    use serde_derive::{Deserialize, Serialize};
+    use tempfile::tempdir;
+
+    #[derive(Serialize, Deserialize)]
+    struct X {
+        x: u32,
+    }
+
+    async fn save_get_delete(db: AsyncStore) {
+        let id = db.save(&X { x: 3 }).await.unwrap();
+        db.save_with_id(&X { x: 4 }, "foo").await.unwrap();
+        assert_eq!(db.get::<X>(&id).await.unwrap().x, 3);
+        assert_eq!(db.all::<X>().await.unwrap().len(), 2);
+        let res = db
+            .range::<X>("foo"..="foo", ScanOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(res[0].1.x, 4);
+        db.delete(&id).await.unwrap();
+        let res = db.get::<X>(&id).await;
+        assert_eq!(res.err().unwrap().kind(), ErrorKind::NotFound);
+        assert!(db.verify::<X>().await.unwrap().is_ok());
+        assert_eq!(db.blocking().get::<X>("foo").unwrap().x, 4);
+    }
+
+    #[tokio::test]
+    async fn dir() {
+        let dir = tempdir().unwrap();
+        let db = AsyncStore::new(dir.path()).await.unwrap();
+        assert_eq!(db.path(), dir.path());
+        save_get_delete(db).await;
+    }
+
+    #[tokio::test]
+    async fn single_file() {
+        let dir = tempdir().unwrap();
+        let mut cfg = Config::default();
+        cfg.single = true;
+        let db = AsyncStore::new_with_cfg(dir.path().join("db"), cfg)
+            .await
+            .unwrap();
+        assert_eq!(db.path(), dir.path().join("db.json"));
+        save_get_delete(db).await;
+    }
+
+    #[tokio::test]
+    async fn in_memory() {
+        let db = AsyncStore::new(IN_MEMORY).await.unwrap();
+        save_get_delete(db.clone()).await;
+        assert_eq!(db.all::<X>().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn sweeper() {
+        let db = AsyncStore::new(IN_MEMORY).await.unwrap();
+        db.save_with_ttl(&X { x: 1 }, "foo", Duration::ZERO)
+            .await
+            .unwrap();
+        let sweeper = db.spawn_sweeper(Duration::from_millis(10));
+        std::thread::sleep(Duration::from_millis(100));
+        drop(sweeper);
+        assert_eq!(db.purge_expired().await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn clock() {
+        #[derive(Debug)]
+        struct FixedClock;
+
+        impl Clock for FixedClock {
+            fn now(&self) -> std::time::SystemTime {
+                std::time::UNIX_EPOCH + Duration::from_secs(5)
+            }
+        }
+
+        let mut cfg = Config::default();
+        cfg.metadata = true;
+        let db = AsyncStore::new_with_clock(IN_MEMORY, cfg, Arc::new(FixedClock))
+            .await
+            .unwrap();
+        db.save_with_id(&X { x: 1 }, "foo").await.unwrap();
+        let meta = db.meta("foo").await.unwrap();
+        assert_eq!(meta.created_at, Some(FixedClock.now()));
+    }
+
+    #[tokio::test]
+    async fn persist_and_load() {
+        let dir = tempdir().unwrap();
+        let db = AsyncStore::new(IN_MEMORY).await.unwrap();
+        db.save_with_id(&X { x: 1 }, "foo").await.unwrap();
+        db.persist_to(dir.path()).await.unwrap();
+        let loaded = AsyncStore::load_into_memory(dir.path(), Config::default())
+            .await
+            .unwrap();
+        assert_eq!(loaded.path(), Path::new(IN_MEMORY));
+        assert_eq!(loaded.get::<X>("foo").await.unwrap().x, 1);
+    }
+
+    #[tokio::test]
+    async fn convert() {
+        let dir = tempdir().unwrap();
+        let mut cfg = Config::default();
+        cfg.single = true;
+        let db = AsyncStore::new_with_cfg(dir.path().join("db"), cfg)
+            .await
+            .unwrap();
+        db.save_with_id(&X { x: 1 }, "foo").await.unwrap();
+        let target = db
+            .convert(dir.path().join("dir"), Config::default())
+            .await
+            .unwrap();
+        assert_eq!(target.path(), dir.path().join("dir"));
+        assert!(dir.path().join("dir/foo.json").exists());
+        assert_eq!(target.get::<X>("foo").await.unwrap().x, 1);
+    }
+}
@@ -0,0 +1,51 @@
+use std::{
+    io::Result,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::{Store, Sweeper};
+
+/// Handle of a background thread that periodically writes a snapshot
+/// of a store to disk (see `Store::autosave`).
+///
+/// A final snapshot is written when the handle is dropped.
+#[derive(Debug)]
+pub struct Autosave {
+    store: Store,
+    path: PathBuf,
+    sweeper: Option<Sweeper>,
+}
+
+impl Autosave {
+    pub(crate) fn new(store: Store, path: PathBuf, interval: Option<Duration>) -> Self {
+        let sweeper = interval.map(|interval| {
+            let store = store.clone();
+            let path = path.clone();
+            Sweeper::spawn(interval, move || persist(&store, &path))
+        });
+        Self {
+            store,
+            path,
+            sweeper,
+        }
+    }
+
+    /// Writes a snapshot right away.
+    pub fn save(&self) -> Result<()> {
+        self.store.persist_to(&self.path)
+    }
+}
+
+impl Drop for Autosave {
+    fn drop(&mut self) {
+        drop(self.sweeper.take());
+        persist(&self.store, &self.path);
+    }
+}
+
+fn persist(store: &Store, path: &Path) {
+    if let Err(err) = store.persist_to(path) {
+        log::warn!("Could not save snapshot to {}: {err}", path.display());
+    }
+}
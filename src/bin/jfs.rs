@@ -0,0 +1,354 @@
+//! Command-line access to a `jfs` store.
+//!
+//! All operations go through [`jfs::Store`] so they honour the same file
+//! locking as any other process using the store.
+
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{self, Error, ErrorKind, Read, Result, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use clap::{Parser, Subcommand};
+use jfs::{Config, Store};
+use serde_json::Value;
+
+#[derive(Debug, Parser)]
+#[command(name = "jfs", version, about = "Inspect and modify a JSON file store")]
+struct Cli {
+    /// Path to the store directory (or file with `--single`)
+    path: PathBuf,
+
+    /// Store all documents in one single JSON file
+    #[arg(long, global = true)]
+    single: bool,
+
+    /// Pretty print written documents
+    #[arg(long, global = true)]
+    pretty: bool,
+
+    /// Number of spaces used for indention when pretty printing
+    #[arg(long, global = true, default_value_t = 2)]
+    indent: usize,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Print a single document
+    Get { id: String },
+    /// Store a document read from a file or stdin
+    Put {
+        id: String,
+        /// File to read the document from (stdin if omitted)
+        file: Option<PathBuf>,
+    },
+    /// Delete a document
+    Delete { id: String },
+    /// List the IDs of all documents
+    Ls,
+    /// Print all documents, one per line, prefixed with their ID
+    CatAll,
+    /// Write all documents as one JSON object to a file or stdout
+    Export {
+        /// Target file (stdout if omitted)
+        file: Option<PathBuf>,
+    },
+    /// Store all documents of a JSON object read from a file or stdin
+    Import {
+        /// File to read from (stdin if omitted)
+        file: Option<PathBuf>,
+    },
+    /// Rewrite all documents using the `--pretty` and `--indent` settings
+    Pretty,
+    /// Print some statistics about the store
+    Stats,
+    /// Copy all documents into a store with another layout
+    ///
+    /// Run it again to resume an interrupted conversion.
+    Convert {
+        /// Path of the target store
+        to: PathBuf,
+        /// Store the target in one single JSON file
+        /// (the directory layout is used otherwise)
+        #[arg(long)]
+        to_single: bool,
+    },
+}
+
+impl Command {
+    const fn creates_store(&self) -> bool {
+        matches!(self, Self::Put { .. } | Self::Import { .. })
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match run(cli, &mut io::stdout().lock()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("jfs: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run<W: Write>(cli: Cli, stdout: &mut W) -> Result<()> {
+    let cfg = Config {
+        single: cli.single,
+        pretty: cli.pretty,
+        indent: cli.indent,
+        ..Config::default()
+    };
+
+    let path = if cfg.single {
+        cli.path.with_extension("json")
+    } else {
+        cli.path.clone()
+    };
+    if !cli.command.creates_store() && !path.exists() {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            format!("no store found at {}", path.display()),
+        ));
+    }
+    let store = Store::new_with_cfg(&cli.path, cfg)?;
+
+    match cli.command {
+        Command::Get { id } => {
+            let doc = store.get::<Value>(&id)?;
+            writeln!(stdout, "{}", to_string(&doc, cli.pretty)?)?;
+        }
+        Command::Put { id, file } => {
+            let doc: Value = from_str(&read_input(file.as_deref())?)?;
+            store.save_with_id(&doc, &id)?;
+        }
+        Command::Delete { id } => store.delete(&id)?,
+        Command::Ls => {
+            for id in store.keys()? {
+                writeln!(stdout, "{id}")?;
+            }
+        }
+        Command::CatAll => {
+            for (id, doc) in store.all::<Value>()? {
+                writeln!(stdout, "{id}\t{}", to_string(&doc, false)?)?;
+            }
+        }
+        Command::Export { file } => {
+            let all = store.all::<Value>()?;
+            let json = to_string(&all, cli.pretty)?;
+            match file {
+                Some(file) => fs::write(file, json)?,
+                None => writeln!(stdout, "{json}")?,
+            }
+        }
+        Command::Import { file } => {
+            let docs: BTreeMap<String, Value> = from_str(&read_input(file.as_deref())?)?;
+            save_all(&store, &docs)?;
+        }
+        Command::Pretty => save_all(&store, &store.all::<Value>()?)?,
+        Command::Stats => {
+            let count = store.count()?;
+            writeln!(stdout, "path:      {}", store.path().display())?;
+            writeln!(
+                stdout,
+                "layout:    {}",
+                if cfg.single { "single" } else { "directory" }
+            )?;
+            writeln!(stdout, "documents: {count}")?;
+            writeln!(
+                stdout,
+                "bytes:     {}",
+                size_on_disk(store.path(), cfg.single)?
+            )?;
+        }
+        Command::Convert { to, to_single } => {
+            let target = store.convert(
+                &to,
+                Config {
+                    single: to_single,
+                    ..cfg
+                },
+            )?;
+            writeln!(
+                stdout,
+                "converted {} documents to {}",
+                target.count()?,
+                target.path().display()
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Saves the documents in one go, so a single-file store is written once.
+fn save_all(store: &Store, docs: &BTreeMap<String, Value>) -> Result<()> {
+    let docs = docs
+        .iter()
+        .map(|(id, doc)| (id.as_str(), doc.clone()))
+        .collect::<Vec<_>>();
+    for res in store.save_many(&docs)? {
+        res?;
+    }
+    Ok(())
+}
+
+fn read_input(file: Option<&Path>) -> Result<String> {
+    let mut buffer = String::new();
+    match file {
+        Some(file) => {
+            File::open(file)?.read_to_string(&mut buffer)?;
+        }
+        None => {
+            io::stdin().read_to_string(&mut buffer)?;
+        }
+    }
+    Ok(buffer)
+}
+
+fn from_str<T>(s: &str) -> Result<T>
+where
+    for<'de> T: serde::Deserialize<'de>,
+{
+    serde_json::from_str(s).map_err(|err| Error::new(ErrorKind::InvalidData, err))
+}
+
+fn to_string<T: serde::Serialize>(value: &T, pretty: bool) -> Result<String> {
+    if pretty {
+        serde_json::to_string_pretty(value)
+    } else {
+        serde_json::to_string(value)
+    }
+    .map_err(|err| Error::new(ErrorKind::InvalidData, err))
+}
+
+fn size_on_disk(path: &Path, single: bool) -> Result<u64> {
+    if single {
+        return Ok(fs::metadata(path)?.len());
+    }
+    let mut size = 0;
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let meta = entry.metadata()?;
+        if meta.is_file() && entry.path().extension().is_some_and(|ext| ext == "json") {
+            size += meta.len();
+        }
+    }
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn jfs(args: &[&str]) -> Result<String> {
+        let cli = Cli::try_parse_from(std::iter::once("jfs").chain(args.iter().copied()))
+            .map_err(|err| Error::new(ErrorKind::InvalidInput, err))?;
+        let mut out = vec![];
+        run(cli, &mut out)?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    fn round_trip(layout: &[&str]) {
+        let dir = tempdir().unwrap();
+        let db = dir.path().join("db");
+        let db = db.to_str().unwrap();
+        let doc = dir.path().join("doc.json");
+        let export = dir.path().join("export.json");
+        let cmd = |args: &[&str]| jfs(&[&[db], layout, args].concat());
+
+        assert!(cmd(&["ls"]).is_err());
+        fs::write(&doc, r#"{"foo":"bar"}"#).unwrap();
+        cmd(&["put", "a", doc.to_str().unwrap()]).unwrap();
+        assert_eq!(cmd(&["get", "a"]).unwrap(), "{\"foo\":\"bar\"}\n");
+        assert!(cmd(&["get", "b"]).is_err());
+
+        fs::write(&doc, r#"{"b":{"x":1},"c":[2]}"#).unwrap();
+        cmd(&["import", doc.to_str().unwrap()]).unwrap();
+        assert_eq!(cmd(&["ls"]).unwrap(), "a\nb\nc\n");
+        assert!(cmd(&["import", export.to_str().unwrap()]).is_err());
+
+        cmd(&["delete", "c"]).unwrap();
+        cmd(&["export", export.to_str().unwrap()]).unwrap();
+        assert_eq!(
+            fs::read_to_string(&export).unwrap(),
+            r#"{"a":{"foo":"bar"},"b":{"x":1}}"#
+        );
+        assert_eq!(
+            cmd(&["export"]).unwrap(),
+            "{\"a\":{\"foo\":\"bar\"},\"b\":{\"x\":1}}\n"
+        );
+        assert_eq!(
+            cmd(&["cat-all"]).unwrap(),
+            "a\t{\"foo\":\"bar\"}\nb\t{\"x\":1}\n"
+        );
+
+        let stats = cmd(&["stats"]).unwrap();
+        let layout_name = if layout.is_empty() {
+            "directory"
+        } else {
+            "single"
+        };
+        assert!(stats.contains(&format!("layout:    {layout_name}\n")));
+        assert!(stats.contains("documents: 2\n"));
+
+        cmd(&["--pretty", "pretty"]).unwrap();
+        let file = if layout.is_empty() {
+            dir.path().join("db/a.json")
+        } else {
+            dir.path().join("db.json")
+        };
+        assert!(fs::read_to_string(file)
+            .unwrap()
+            .contains("\"foo\": \"bar\""));
+        assert_eq!(cmd(&["get", "a"]).unwrap(), "{\"foo\":\"bar\"}\n");
+    }
+
+    #[test]
+    fn directory() {
+        round_trip(&[]);
+    }
+
+    #[test]
+    fn convert() {
+        let dir = tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_owned();
+        let (db, to, back) = (path("db"), path("dir"), path("back"));
+        let input = dir.path().join("docs.json");
+        fs::write(&input, r#"{"a":{"x":1},"b":[2]}"#).unwrap();
+        jfs(&[&db, "--single", "import", input.to_str().unwrap()]).unwrap();
+
+        assert_eq!(
+            jfs(&[&db, "--single", "convert", &to]).unwrap(),
+            format!("converted 2 documents to {to}\n")
+        );
+        assert_eq!(jfs(&[&to, "ls"]).unwrap(), "a\nb\n");
+        assert_eq!(jfs(&[&to, "get", "b"]).unwrap(), "[2]\n");
+
+        jfs(&[&to, "convert", &back, "--to-single"]).unwrap();
+        assert_eq!(
+            jfs(&[&back, "--single", "export"]).unwrap(),
+            "{\"a\":{\"x\":1},\"b\":[2]}\n"
+        );
+    }
+
+    #[test]
+    fn ls_lists_unreadable_documents() {
+        let dir = tempdir().unwrap();
+        let db = dir.path().join("db");
+        fs::create_dir(&db).unwrap();
+        fs::write(db.join("a.json"), "{}").unwrap();
+        fs::write(db.join("b.json"), "not json").unwrap();
+        assert_eq!(jfs(&[db.to_str().unwrap(), "ls"]).unwrap(), "a\nb\n");
+    }
+
+    #[test]
+    fn single() {
+        round_trip(&["--single"]);
+    }
+}
@@ -0,0 +1,80 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use serde_json::Value;
+
+use crate::{json_store::JsonStore, memory_store::MemoryStore, meta::Clock, Config};
+
+/// Hit and miss counters of the document cache of a store
+/// (see `Config::cache`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+/// An LRU cache of decoded documents in front of a directory store.
+///
+/// It's shared by all clones of the store.
+#[derive(Debug, Clone)]
+pub struct Cache {
+    docs: MemoryStore,
+    hits: Arc<AtomicU64>,
+    misses: Arc<AtomicU64>,
+}
+
+impl Cache {
+    pub fn new(capacity: usize, clock: Arc<dyn Clock>) -> Self {
+        let cfg = Config {
+            parsed: true,
+            max_entries: Some(capacity),
+            ..Config::default()
+        };
+        Self {
+            docs: MemoryStore::new(cfg, clock),
+            hits: Arc::default(),
+            misses: Arc::default(),
+        }
+    }
+
+    pub fn get(&self, id: &str) -> Option<Value> {
+        if let Ok(doc) = self.docs.get_value(id) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            Some(doc)
+        } else {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+            None
+        }
+    }
+
+    /// Caches a document that expires after `ttl`.
+    pub fn put(&self, id: &str, doc: &Value, ttl: Option<Duration>) {
+        let res = match ttl {
+            Some(ttl) => self.docs.save_with_ttl(doc, id, ttl),
+            None => self.docs.save_with_id(doc, id),
+        };
+        if res.is_err() {
+            self.remove(id);
+        }
+    }
+
+    pub fn remove(&self, id: &str) {
+        let _ = self.docs.delete(id);
+    }
+
+    pub fn clear(&self) {
+        let _ = self.docs.clear();
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use parking_lot::RwLock;
+use serde_json::Value;
+
+/// Which documents an in-memory store drops first when it's full
+/// (see `Config::max_entries` and `Config::max_bytes`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Eviction {
+    /// Drop the least recently used document.
+    #[default]
+    Lru,
+    /// Drop the least frequently used document.
+    Lfu,
+}
+
+/// How recently and how often a document was used.
+///
+/// The counters are atomic so that reads can update them
+/// while only holding a read lock on the store.
+#[derive(Debug, Default)]
+pub struct Usage {
+    last_used: AtomicU64,
+    uses: AtomicU64,
+}
+
+impl Usage {
+    pub fn touch(&self, tick: u64) {
+        self.last_used.store(tick, Ordering::Relaxed);
+        self.uses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// The document with the lowest rank is evicted first.
+    pub fn rank(&self, policy: Eviction) -> Rank {
+        let last_used = self.last_used.load(Ordering::Relaxed);
+        let uses = self.uses.load(Ordering::Relaxed);
+        match policy {
+            Eviction::Lru => (last_used, uses),
+            Eviction::Lfu => (uses, last_used),
+        }
+    }
+}
+
+pub type Rank = (u64, u64);
+
+/// The documents that are evicted next, so that not every eviction
+/// needs to scan the whole store.
+///
+/// All other documents rank at least `threshold`. Ranks only grow,
+/// so the lowest candidate whose rank is still current ranks lowest
+/// in the whole store.
+#[derive(Debug, Default)]
+pub struct Candidates {
+    heap: BinaryHeap<Reverse<(Rank, String)>>,
+    threshold: Rank,
+}
+
+impl Candidates {
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    pub fn pop(&mut self) -> Option<(Rank, String)> {
+        self.heap.pop().map(|Reverse(c)| c)
+    }
+
+    /// Adds a document if it ranks below all non-candidates,
+    /// e.g. a new one or one whose rank changed since it was added.
+    pub fn offer(&mut self, rank: Rank, id: String) {
+        if rank < self.threshold {
+            self.heap.push(Reverse((rank, id)));
+        }
+    }
+
+    /// Replaces the candidates with the lowest ranked eighth of `ranked`.
+    ///
+    /// Returns false if `ranked` is empty.
+    pub fn refill(&mut self, mut ranked: Vec<(Rank, &String)>) -> bool {
+        if ranked.is_empty() {
+            return false;
+        }
+        let n = (ranked.len() / 8).max(1);
+        self.threshold = if n < ranked.len() {
+            let (_, &mut (threshold, _), _) = ranked.select_nth_unstable(n);
+            ranked.truncate(n);
+            threshold
+        } else {
+            (u64::MAX, u64::MAX)
+        };
+        self.heap = ranked
+            .into_iter()
+            .map(|(rank, id)| Reverse((rank, id.clone())))
+            .collect();
+        true
+    }
+}
+
+type Callback = Arc<dyn Fn(&str, Value) + Send + Sync>;
+
+/// The eviction callback of a store (see `Store::on_evict`).
+///
+/// It's shared by all clones of the store.
+#[derive(Clone, Default)]
+pub struct Listener {
+    callback: Arc<RwLock<Option<Callback>>>,
+}
+
+impl Listener {
+    pub fn set(&self, callback: Callback) {
+        *self.callback.write() = Some(callback);
+    }
+
+    pub fn get(&self) -> Option<Callback> {
+        self.callback.read().clone()
+    }
+}
+
+impl fmt::Debug for Listener {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Listener")
+            .field("callback", &self.callback.read().is_some())
+            .finish()
+    }
+}
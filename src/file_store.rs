@@ -1,10 +1,12 @@
 use std::{
-    collections::BTreeMap,
+    collections::{BTreeMap, HashSet},
     fs::{create_dir_all, metadata, read_dir, remove_file, rename, OpenOptions},
     io::{
         Read, Write, {Error, ErrorKind, Result},
     },
     path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
 };
 
 use fs2::FileExt;
@@ -16,15 +18,84 @@ use serde_json::{
 };
 use uuid::Uuid;
 
-use crate::json_store::JsonStore;
+use crate::{
+    cache::{Cache, CacheStats},
+    evict::Eviction,
+    history::{self, Archived, Retention, Version},
+    json_store::{Decoded, JsonStore},
+    meta::{now_millis, Clock, Meta, Metadata},
+    scan::{self, ScanOptions},
+    schema::Schema,
+    trash::{TrashEntry, Trashed},
+    verify::{Issue, RepairPolicy, Report},
+    write_behind::{Buffer, WriteBehind},
+};
 
 type Object = Map<String, Value>;
 
+const TMP_EXTENSION: &str = "tmp";
+
+/// Subdirectory that holds the metadata of documents in directory mode.
+const META_DIR: &str = ".meta";
+
+/// Subdirectory that holds prior versions of documents in directory mode.
+const HISTORY_DIR: &str = ".history";
+
+/// Subdirectory that holds soft-deleted documents in directory mode.
+const TRASH_DIR: &str = ".trash";
+
+/// Temporary files younger than this are never treated as orphans.
+const STALE_TMP_FILE_AGE: Duration = Duration::from_secs(60);
+
 #[derive(Debug, Clone, Copy)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct Config {
     pub pretty: bool,
     pub indent: usize,
     pub single: bool,
+    /// Record creation time, modification time and revision of
+    /// every document (see `Store::meta`).
+    pub metadata: bool,
+    /// Keep prior versions of documents when they're saved or deleted
+    /// (see `Store::history`).
+    pub history: Option<Retention>,
+    /// Move deleted documents into a trash area instead of removing
+    /// them (see `Store::restore`).
+    pub trash: bool,
+    /// Keep the documents of an in-memory store as parsed JSON values
+    /// instead of JSON text. This speeds up reads at the cost of memory.
+    /// File stores ignore this setting.
+    pub parsed: bool,
+    /// Maximum number of documents an in-memory store keeps before it
+    /// evicts documents (see `eviction`). File stores ignore this setting.
+    pub max_entries: Option<usize>,
+    /// Maximum total size in bytes of the JSON text of the documents an
+    /// in-memory store keeps before it evicts documents (see `eviction`).
+    /// File stores ignore this setting.
+    pub max_bytes: Option<usize>,
+    /// Which documents are evicted first if `max_entries` or `max_bytes`
+    /// is exceeded.
+    pub eviction: Eviction,
+    /// Keep up to this many recently read documents of a directory store
+    /// decoded in memory. Reads of cached documents don't touch the disk,
+    /// so the store must not be modified by anyone else.
+    /// Single-file and in-memory stores ignore this setting.
+    pub cache: Option<usize>,
+    /// Buffer saved documents of a single-file store in memory and write
+    /// them in one go later (see `Store::flush`).
+    /// Directory and in-memory stores ignore this setting.
+    ///
+    /// Saving doesn't fail if writing the buffered documents fails; they
+    /// stay buffered and the error is only returned by `Store::flush`.
+    /// Documents still pending when the store is dropped are written then;
+    /// if that fails, they are saved to a new file in `<stem>.recovery`
+    /// next to the store. Call `Store::flush` before dropping the store
+    /// to handle such errors yourself.
+    pub write_behind: Option<WriteBehind>,
+    /// Open an existing file store without modifying it: nothing is created
+    /// or cleaned up, only shared locks are taken and all writes fail with
+    /// `ErrorKind::PermissionDenied`. In-memory stores ignore this setting.
+    pub read_only: bool,
 }
 
 impl Default for Config {
@@ -33,6 +104,16 @@ impl Default for Config {
             indent: 2,
             pretty: false,
             single: false,
+            metadata: false,
+            history: None,
+            trash: false,
+            parsed: false,
+            max_entries: None,
+            max_bytes: None,
+            eviction: Eviction::Lru,
+            cache: None,
+            write_behind: None,
+            read_only: false,
         }
     }
 }
@@ -41,6 +122,10 @@ impl Default for Config {
 pub struct FileStore {
     path: PathBuf,
     cfg: Config,
+    clock: Arc<dyn Clock>,
+    schema: Schema,
+    cache: Option<Cache>,
+    write_behind: Option<Arc<Buffer>>,
 }
 
 impl JsonStore for FileStore {
@@ -56,97 +141,1019 @@ impl JsonStore for FileStore {
     where
         for<'de> T: Serialize + Deserialize<'de>,
     {
+        self.check_writable()?;
+        if let Some(buffer) = &self.write_behind {
+            self.schema.check(obj)?;
+            let doc = serde_json::to_value(obj).map_err(|err| Error::new(ErrorKind::Other, err))?;
+            buffer.push(id, doc);
+            return Ok(id.to_owned());
+        }
+        self.save_document(obj, id, None)?;
+        Ok(id.to_owned())
+    }
+
+    fn save_with_ttl<T>(&self, obj: &T, id: &str, ttl: Duration) -> Result<String>
+    where
+        for<'de> T: Serialize + Deserialize<'de>,
+    {
+        self.check_writable()?;
+        self.flush()?;
+        self.save_document(obj, id, Some(ttl))?;
+        Ok(id.to_owned())
+    }
+
+    fn save_many<T>(&self, docs: &[(&str, T)]) -> Result<Vec<Result<String>>>
+    where
+        for<'de> T: Serialize + Deserialize<'de>,
+    {
+        self.check_writable()?;
+        if !self.cfg.single || self.write_behind.is_some() {
+            return Ok(docs
+                .iter()
+                .map(|(id, obj)| self.save_with_id(obj, id))
+                .collect());
+        }
+        let mut changes = vec![];
+        let mut results = vec![];
+        for (id, obj) in docs {
+            let json = self.schema.check(obj).and_then(|()| {
+                serde_json::to_value(obj).map_err(|err| Error::new(ErrorKind::Other, err))
+            });
+            match json {
+                Ok(json) => {
+                    changes.push((*id, Some(json)));
+                    results.push(Ok((*id).to_owned()));
+                }
+                Err(err) => results.push(Err(err)),
+            }
+        }
+        self.apply_changes(changes)?;
+        Ok(results)
+    }
+
+    fn get<T>(&self, id: &str) -> Result<T>
+    where
+        for<'de> T: Deserialize<'de>,
+    {
+        decode(self.get_value(id)?)
+    }
+
+    fn get_value(&self, id: &str) -> Result<Value> {
+        if let Some(doc) = self.write_behind.as_ref().and_then(|b| b.get(id)) {
+            return Ok(doc);
+        }
+        if let Some(doc) = self.cache.as_ref().and_then(|c| c.get(id)) {
+            return Ok(doc);
+        }
+        let now = self.now();
+        let meta = self.load_meta(id)?;
+        if meta.as_ref().is_some_and(|m| m.is_expired(now)) {
+            return Err(Error::new(ErrorKind::NotFound, "no such object"));
+        }
+        let doc = self.read_document(id)?;
+        if let Some(cache) = &self.cache {
+            cache.put(id, &doc, meta.and_then(|m| m.ttl(now)));
+        }
+        Ok(doc)
+    }
+
+    fn exists(&self, id: &str) -> Result<bool> {
+        if self
+            .write_behind
+            .as_ref()
+            .is_some_and(|b| b.get(id).is_some())
+        {
+            return Ok(true);
+        }
+        let found = if self.cfg.single {
+            let json = get_json_from_file(&self.path)?;
+            get_object_from_json(&json)?.contains_key(id)
+        } else {
+            self.id_to_path(id).is_file()
+        };
+        Ok(found
+            && !self
+                .load_meta(id)?
+                .is_some_and(|m| m.is_expired(self.now())))
+    }
+
+    fn keys(&self) -> Result<Vec<String>> {
+        self.flush()?;
+        let now = self.now();
+        let meta = self.load_all_meta()?;
+        let mut ids = self.stored_ids()?;
+        ids.retain(|id| !meta.get(id).is_some_and(|m| m.is_expired(now)));
+        ids.sort();
+        Ok(ids)
+    }
+
+    fn scan<T>(&self, filter: &dyn Fn(&str) -> bool, opts: ScanOptions) -> Result<Vec<(String, T)>>
+    where
+        for<'de> T: Deserialize<'de>,
+    {
+        self.flush()?;
+        let limit = opts.limit.unwrap_or(usize::MAX);
+        let now = self.now();
+        let mut result = vec![];
+        if self.cfg.single {
+            let meta = self.load_all_meta()?;
+            let json = get_json_from_file(&self.path)?;
+            let object = get_object_from_json(&json)?;
+            for id in scan::select(object.keys().map(String::as_str), filter, opts) {
+                if result.len() == limit {
+                    break;
+                }
+                if meta.get(id).is_some_and(|m| m.is_expired(now)) {
+                    continue;
+                }
+                match decode(object[id].clone()) {
+                    Ok(x) => result.push((id.to_owned(), x)),
+                    Err(err) => log::warn!("Skipping document '{id}': {err}"),
+                }
+            }
+            return Ok(result);
+        }
+        let ids = self.ids()?;
+        for id in scan::select(ids.iter().map(String::as_str), filter, opts) {
+            if result.len() == limit {
+                break;
+            }
+            if self.load_meta(id)?.is_some_and(|m| m.is_expired(now)) {
+                continue;
+            }
+            match self.read_document(id).and_then(decode) {
+                Ok(x) => result.push((id.to_owned(), x)),
+                // The document was deleted in the meantime.
+                Err(err) if err.kind() == ErrorKind::NotFound => {}
+                Err(err) => log::warn!("Skipping document '{id}': {err}"),
+            }
+        }
+        Ok(result)
+    }
+
+    #[cfg(feature = "jsonschema")]
+    fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    fn update<F>(&self, id: &str, f: F) -> Result<()>
+    where
+        F: FnOnce(&mut Value) -> Result<()>,
+    {
+        self.check_writable()?;
+        self.flush()?;
+        let now = self.now();
+        let meta = self.load_meta(id)?;
+        if meta.as_ref().is_some_and(|m| m.is_expired(now)) {
+            return Err(Error::new(ErrorKind::NotFound, "no such object"));
+        }
+        let mut doc = self.read_document(id)?;
+        f(&mut doc)?;
+        self.save_document(&doc, id, meta.and_then(|m| m.ttl(now)))
+    }
+
+    fn meta(&self, id: &str) -> Result<Metadata> {
+        self.flush()?;
+        let meta = self.load_meta(id)?.unwrap_or_default();
+        if meta.is_expired(self.now()) {
+            return Err(Error::new(ErrorKind::NotFound, "no such object"));
+        }
+        let size = if self.cfg.single {
+            let json = self.read_document(id)?;
+            serde_json::to_string(&json)
+                .map_err(|err| Error::new(ErrorKind::Other, err))?
+                .len() as u64
+        } else {
+            metadata(self.id_to_path(id))?.len()
+        };
+        Ok(meta.to_metadata(size))
+    }
+
+    fn history(&self, id: &str) -> Result<Vec<Version>> {
+        self.flush()?;
+        Ok(self
+            .load_history(id)?
+            .iter()
+            .map(Archived::to_version)
+            .collect())
+    }
+
+    fn get_many<T>(&self, ids: &[&str]) -> Result<Vec<Result<T>>>
+    where
+        for<'de> T: Deserialize<'de>,
+    {
+        self.flush()?;
+        if !self.cfg.single {
+            return Ok(ids.iter().map(|id| self.get(id)).collect());
+        }
+        let now = self.now();
+        let meta = self.load_all_meta()?;
+        let json = get_json_from_file(&self.path)?;
+        let object = get_object_from_json(&json)?;
+        Ok(ids
+            .iter()
+            .map(|id| {
+                object
+                    .get(*id)
+                    .filter(|_| !meta.get(*id).is_some_and(|m| m.is_expired(now)))
+                    .ok_or_else(|| Error::new(ErrorKind::NotFound, "no such object"))
+                    .and_then(|value| decode(value.clone()))
+            })
+            .collect())
+    }
+
+    fn get_at<T>(&self, id: &str, version: u64) -> Result<T>
+    where
+        for<'de> T: Deserialize<'de>,
+    {
+        self.flush()?;
+        self.load_history(id)?
+            .into_iter()
+            .find(|a| a.version == version)
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, "no such version"))
+            .and_then(|a| decode(a.doc))
+    }
+
+    fn all_with_errors<T>(&self) -> Result<Decoded<T>>
+    where
+        for<'de> T: Deserialize<'de>,
+    {
+        self.flush()?;
+        let mut result = BTreeMap::new();
+        let mut errors = vec![];
+        let now = self.now();
+        let meta = self.load_all_meta()?;
+        let is_expired = |id: &str| meta.get(id).is_some_and(|m| m.is_expired(now));
+
+        if self.cfg.single {
+            let json = get_json_from_file(&self.id_to_path(""))?;
+            let object = get_object_from_json(&json)?;
+            for x in object {
+                let (key, value) = x;
+                if is_expired(key) {
+                    continue;
+                }
+                match decode(value.clone()) {
+                    Ok(r) => {
+                        result.insert(key.clone(), r);
+                    }
+                    Err(err) => errors.push((key.clone(), err)),
+                }
+            }
+            return Ok((result, errors));
+        }
+
+        if !metadata(&self.path)?.is_dir() {
+            return Err(Error::new(ErrorKind::NotFound, "invalid path"));
+        }
+
+        for id in self.ids()? {
+            if is_expired(&id) {
+                continue;
+            }
+            match self.read_document(&id).and_then(decode) {
+                Ok(x) => {
+                    result.insert(id, x);
+                }
+                // The document was deleted in the meantime.
+                Err(err) if err.kind() == ErrorKind::NotFound => {}
+                Err(err) => errors.push((id, err)),
+            }
+        }
+
+        Ok((result, errors))
+    }
+
+    fn delete(&self, id: &str) -> Result<()> {
+        self.check_writable()?;
+        self.flush()?;
+        self.uncache(id);
+        if let Some(retention) = &self.cfg.history {
+            self.archive(id, retention)?;
+        }
+        if self.cfg.trash {
+            self.move_to_trash(id)?;
+        }
         if self.cfg.single {
             let json = get_json_from_file(&self.path)?;
             let object = get_object_from_json(&json)?;
             let mut x = object.clone();
-            let json =
-                serde_json::to_value(obj).map_err(|err| Error::new(ErrorKind::Other, err))?;
-            x.insert(id.to_owned(), json);
+            if x.contains_key(id) {
+                x.remove(id);
+            } else {
+                return Err(Error::new(ErrorKind::NotFound, "no such object"));
+            }
             self.save_object_to_file(&x, &self.path)?;
         } else {
-            self.save_object_to_file(obj, &self.id_to_path(id))?;
+            remove_file(self.id_to_path(id))?;
         }
-        Ok(id.to_owned())
+        self.store_meta(id, None)
     }
 
-    fn get<T>(&self, id: &str) -> Result<T>
+    fn delete_many(&self, ids: &[&str]) -> Result<Vec<Result<()>>> {
+        self.check_writable()?;
+        self.flush()?;
+        if !self.cfg.single {
+            return Ok(ids.iter().map(|id| self.delete(id)).collect());
+        }
+        self.apply_changes(ids.iter().map(|id| (*id, None)).collect())
+    }
+
+    fn clear(&self) -> Result<usize> {
+        self.check_writable()?;
+        self.flush()?;
+        let ids = self.stored_ids()?;
+        let ids = ids.iter().map(String::as_str).collect::<Vec<_>>();
+        let mut count = 0;
+        for res in self.delete_many(&ids)? {
+            match res {
+                Ok(()) => count += 1,
+                // The document was deleted in the meantime.
+                Err(err) if err.kind() == ErrorKind::NotFound => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(count)
+    }
+
+    fn restore(&self, id: &str) -> Result<()> {
+        self.check_writable()?;
+        self.flush()?;
+        let trashed = self
+            .load_trash()?
+            .remove(id)
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, "no such object in trash"))?;
+        // Expired documents are invisible to readers and get replaced.
+        let expired = self
+            .load_meta(id)?
+            .is_some_and(|m| m.is_expired(self.now()));
+        match self.read_document(id) {
+            Ok(_) if !expired => {
+                return Err(Error::new(
+                    ErrorKind::AlreadyExists,
+                    "object already exists",
+                ))
+            }
+            Ok(_) => {}
+            Err(err) if err.kind() == ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+        self.save_document(&trashed.doc, id, None)?;
+        self.remove_from_trash(&[id])
+    }
+
+    fn list_trash(&self) -> Result<Vec<TrashEntry>> {
+        self.flush()?;
+        Ok(self
+            .load_trash()?
+            .iter()
+            .map(|(id, t)| t.to_entry(id))
+            .collect())
+    }
+
+    fn empty_trash(&self, older_than: Duration) -> Result<usize> {
+        self.check_writable()?;
+        self.flush()?;
+        let now = self.now();
+        let trash = self.load_trash()?;
+        let ids = trash
+            .iter()
+            .filter(|(_, t)| t.is_older_than(older_than, now))
+            .map(|(id, _)| id.as_str())
+            .collect::<Vec<_>>();
+        self.remove_from_trash(&ids)?;
+        Ok(ids.len())
+    }
+
+    fn purge_expired(&self) -> Result<usize> {
+        self.check_writable()?;
+        self.flush()?;
+        let now = self.now();
+        let expired = self
+            .load_all_meta()?
+            .into_iter()
+            .filter(|(_, m)| m.is_expired(now))
+            .map(|(id, _)| id)
+            .collect::<Vec<_>>();
+        if expired.is_empty() {
+            return Ok(0);
+        }
+        if self.cfg.single {
+            let json = get_json_from_file(&self.path)?;
+            let mut object = get_object_from_json(&json)?.clone();
+            for id in &expired {
+                object.remove(id);
+            }
+            self.save_object_to_file(&object, &self.path)?;
+        } else {
+            for id in &expired {
+                self.uncache(id);
+                if let Err(err) = remove_file(self.id_to_path(id)) {
+                    if err.kind() != ErrorKind::NotFound {
+                        return Err(err);
+                    }
+                }
+            }
+        }
+        let metas = expired
+            .iter()
+            .map(|id| (id.as_str(), None))
+            .collect::<Vec<_>>();
+        self.store_all_meta(&metas)?;
+        Ok(expired.len())
+    }
+
+    fn verify<T>(&self) -> Result<Report>
+    where
+        for<'de> T: Deserialize<'de>,
+    {
+        self.flush()?;
+        let mut report = Report::default();
+        // Finding stale temporary files takes exclusive locks.
+        if !self.cfg.read_only {
+            for dir in self.tmp_dirs() {
+                for path in stale_tmp_files(&dir, &self.tmp_prefix())? {
+                    report.issues.push(Issue::TmpFile { path });
+                }
+            }
+        }
+        if self.cfg.single {
+            let Some(json) = read_json_file(&self.path, &mut report) else {
+                return Ok(report);
+            };
+            let Value::Object(object) = json else {
+                report.issues.push(Issue::NotAnObject {
+                    path: self.path.clone(),
+                });
+                return Ok(report);
+            };
+            for (id, value) in object {
+                if let Err(error) = decode::<T>(value) {
+                    report.issues.push(Issue::Undecodable { id, error });
+                }
+            }
+            return Ok(report);
+        }
+        for entry in read_dir(&self.path)? {
+            let entry = entry?;
+            let path = entry.path();
+            if !entry.file_type()?.is_file() || !is_json_file(&path) {
+                continue;
+            }
+            let id = match path_buf_to_id(&path) {
+                Ok(id) if self.id_to_path(&id) == path => id,
+                _ => {
+                    report.issues.push(Issue::InvalidId { path });
+                    continue;
+                }
+            };
+            if let Some(json) = read_json_file(&path, &mut report) {
+                if let Err(error) = decode::<T>(json) {
+                    report.issues.push(Issue::Undecodable { id, error });
+                }
+            }
+        }
+        Ok(report)
+    }
+
+    fn repair<T>(&self, policy: RepairPolicy) -> Result<Report>
     where
         for<'de> T: Deserialize<'de>,
     {
+        self.check_writable()?;
+        self.flush()?;
+        let report = self.verify::<T>()?;
+        if !self.cfg.single {
+            if let Some(cache) = &self.cache {
+                cache.clear();
+            }
+            for issue in &report.issues {
+                match issue {
+                    Issue::TmpFile { path }
+                    | Issue::Malformed { path, .. }
+                    | Issue::InvalidId { path } => self.discard_file(path, policy)?,
+                    Issue::Undecodable { id, .. } => {
+                        self.discard_file(&self.id_to_path(id), policy)?;
+                    }
+                    Issue::Unreadable { .. } | Issue::NotAnObject { .. } => {}
+                }
+            }
+            return Ok(report);
+        }
+        let mut broken = false;
+        let mut undecodable = vec![];
+        for issue in &report.issues {
+            match issue {
+                Issue::TmpFile { path } => self.discard_file(path, policy)?,
+                Issue::Malformed { .. } | Issue::NotAnObject { .. } => broken = true,
+                Issue::Undecodable { id, .. } => undecodable.push(id),
+                Issue::Unreadable { .. } | Issue::InvalidId { .. } => {}
+            }
+        }
+        if broken {
+            self.discard_file(&self.path, policy)?;
+            self.save_object_to_file(&Object::new(), &self.path)?;
+        } else if !undecodable.is_empty() {
+            let json = get_json_from_file(&self.path)?;
+            let mut object = get_object_from_json(&json)?.clone();
+            let mut removed = Object::new();
+            for id in undecodable {
+                if let Some(value) = object.remove(id) {
+                    removed.insert(id.clone(), value);
+                }
+            }
+            // IDs aren't necessarily valid file names, so the documents are
+            // quarantined together in one file that maps IDs to documents.
+            if policy == RepairPolicy::Quarantine && !removed.is_empty() {
+                let dir = self.quarantine_dir();
+                create_dir_all(&dir)?;
+                let file_name = dir.join(Uuid::new_v4().to_string()).with_extension("json");
+                self.save_object_to_file(&removed, &file_name)?;
+            }
+            self.save_object_to_file(&object, &self.path)?;
+        }
+        Ok(report)
+    }
+}
+
+impl FileStore {
+    /// Lists the IDs of all documents in directory mode.
+    ///
+    /// Only regular files with a `.json` extension are considered to be
+    /// documents; anything else in the directory is ignored.
+    fn ids(&self) -> Result<Vec<String>> {
+        let ids = read_dir(&self.path)?
+            .filter_map(|e| {
+                e.and_then(|x| {
+                    x.metadata().and_then(|m| {
+                        if m.is_file() && is_json_file(&x.path()) {
+                            path_buf_to_id(&x.path())
+                        } else {
+                            Err(Error::new(ErrorKind::Other, "not a JSON file"))
+                        }
+                    })
+                })
+                .ok()
+            })
+            .collect();
+        Ok(ids)
+    }
+
+    /// Lists the IDs of all documents, including expired ones.
+    fn stored_ids(&self) -> Result<Vec<String>> {
+        if self.cfg.single {
+            let json = get_json_from_file(&self.path)?;
+            return Ok(get_object_from_json(&json)?.keys().cloned().collect());
+        }
+        self.ids()
+    }
+
+    /// The file name prefix of temporary files that belong to this store.
+    ///
+    /// In single-file mode they live next to other files, so only those
+    /// named after the store are considered.
+    fn tmp_prefix(&self) -> String {
+        if self.cfg.single {
+            let stem = self.path.file_stem().unwrap_or_default();
+            format!("{}.", stem.to_string_lossy())
+        } else {
+            String::new()
+        }
+    }
+
+    /// The existing directories that may hold temporary files
+    /// created by `save_object_to_file`.
+    fn tmp_dirs(&self) -> Vec<PathBuf> {
+        let dirs = if self.cfg.single {
+            match self.path.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => vec![dir.to_path_buf()],
+                _ => vec![PathBuf::from(".")],
+            }
+        } else {
+            let mut dirs = vec![
+                self.path.clone(),
+                self.path.join(META_DIR),
+                self.path.join(TRASH_DIR),
+            ];
+            if let Ok(entries) = read_dir(self.path.join(HISTORY_DIR)) {
+                dirs.extend(entries.filter_map(Result::ok).map(|e| e.path()));
+            }
+            dirs
+        };
+        dirs.into_iter().filter(|d| d.is_dir()).collect()
+    }
+
+    fn trash_path(&self, id: &str) -> PathBuf {
+        if self.cfg.single {
+            self.path.with_extension("trash.json")
+        } else {
+            self.path.join(TRASH_DIR).join(id).with_extension("json")
+        }
+    }
+
+    fn load_trash(&self) -> Result<BTreeMap<String, Trashed>> {
+        if self.cfg.single {
+            let path = self.trash_path("");
+            if !path.exists() {
+                return Ok(BTreeMap::new());
+            }
+            return decode(get_json_from_file(&path)?);
+        }
+        let dir = self.path.join(TRASH_DIR);
+        if !dir.is_dir() {
+            return Ok(BTreeMap::new());
+        }
+        let mut trash = BTreeMap::new();
+        for entry in read_dir(dir)? {
+            let path = entry?.path();
+            if is_json_file(&path) {
+                let id = path_buf_to_id(&path)?;
+                trash.insert(id, decode(get_json_from_file(&path)?)?);
+            }
+        }
+        Ok(trash)
+    }
+
+    /// Copies a document into the trash.
+    fn move_to_trash(&self, id: &str) -> Result<()> {
+        let doc = self.read_document(id)?;
+        self.trash_documents(vec![(id, doc)])
+    }
+
+    /// Puts the given (already removed or about to be removed) documents into the trash.
+    fn trash_documents(&self, docs: Vec<(&str, Value)>) -> Result<()> {
+        if docs.is_empty() {
+            return Ok(());
+        }
+        let deleted_at = self.now();
+        if self.cfg.single {
+            let mut trash = self.load_trash()?;
+            for (id, doc) in docs {
+                trash.insert(id.to_owned(), Trashed { deleted_at, doc });
+            }
+            return self.save_object_to_file(&trash, &self.trash_path(""));
+        }
+        create_dir_all(self.path.join(TRASH_DIR))?;
+        for (id, doc) in docs {
+            self.save_object_to_file(&Trashed { deleted_at, doc }, &self.trash_path(id))?;
+        }
+        Ok(())
+    }
+
+    fn remove_from_trash(&self, ids: &[&str]) -> Result<()> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+        if self.cfg.single {
+            let mut trash = self.load_trash()?;
+            for id in ids {
+                trash.remove(*id);
+            }
+            return self.save_object_to_file(&trash, &self.trash_path(""));
+        }
+        for id in ids {
+            remove_file(self.trash_path(id))?;
+        }
+        Ok(())
+    }
+
+    fn history_path(&self, id: &str) -> PathBuf {
+        if self.cfg.single {
+            self.path.with_extension("history.json")
+        } else {
+            self.path.join(HISTORY_DIR).join(id)
+        }
+    }
+
+    /// Loads all prior versions of a document, sorted by version number.
+    fn load_history(&self, id: &str) -> Result<Vec<Archived>> {
+        let path = self.history_path(id);
+        if self.cfg.single {
+            if !path.exists() {
+                return Ok(vec![]);
+            }
+            let mut all: BTreeMap<String, Vec<Archived>> = decode(get_json_from_file(&path)?)?;
+            return Ok(all.remove(id).unwrap_or_default());
+        }
+        if !path.is_dir() {
+            return Ok(vec![]);
+        }
+        let mut history = vec![];
+        for entry in read_dir(path)? {
+            let path = entry?.path();
+            if is_json_file(&path) {
+                history.push(decode::<Archived>(get_json_from_file(&path)?)?);
+            }
+        }
+        history.sort_by_key(|a| a.version);
+        Ok(history)
+    }
+
+    /// Stores the current content of a document (if any) as a new prior version.
+    fn archive(&self, id: &str, retention: &Retention) -> Result<()> {
+        let doc = match self.read_document(id) {
+            Ok(doc) => doc,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err),
+        };
+        self.archive_documents(vec![(id, doc)], retention)
+    }
+
+    /// Stores the given contents as new prior versions of their documents.
+    fn archive_documents(&self, docs: Vec<(&str, Value)>, retention: &Retention) -> Result<()> {
+        if docs.is_empty() {
+            return Ok(());
+        }
+        let now = self.now();
+        if self.cfg.single {
+            let path = self.history_path("");
+            let mut all: BTreeMap<String, Vec<Archived>> = if path.exists() {
+                decode(get_json_from_file(&path)?)?
+            } else {
+                BTreeMap::new()
+            };
+            for (id, doc) in docs {
+                history::archive(all.entry(id.to_owned()).or_default(), doc, now, retention);
+            }
+            return self.save_object_to_file(&all, &path);
+        }
+        for (id, doc) in docs {
+            let path = self.history_path(id);
+            let mut history = self.load_history(id)?;
+            let prev = history.iter().map(|a| a.version).collect::<Vec<_>>();
+            history::archive(&mut history, doc, now, retention);
+            create_dir_all(&path)?;
+            let version_path = |v: u64| path.join(v.to_string()).with_extension("json");
+            for a in &history {
+                if !prev.contains(&a.version) {
+                    self.save_object_to_file(a, &version_path(a.version))?;
+                }
+            }
+            for v in prev {
+                if !history.iter().any(|a| a.version == v) {
+                    remove_file(version_path(v))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn quarantine_dir(&self) -> PathBuf {
+        if self.cfg.single {
+            self.path.with_extension("quarantine")
+        } else {
+            self.path.join(".quarantine")
+        }
+    }
+
+    /// Removes or quarantines a file, depending on the `policy`.
+    fn discard_file(&self, path: &Path, policy: RepairPolicy) -> Result<()> {
+        match policy {
+            RepairPolicy::Remove => remove_file(path),
+            RepairPolicy::Quarantine => {
+                let dir = self.quarantine_dir();
+                create_dir_all(&dir)?;
+                let file_name = path.file_name().map(PathBuf::from).unwrap_or_default();
+                rename(path, unused_path(&dir, file_name))
+            }
+        }
+    }
+
+    fn now(&self) -> u64 {
+        now_millis(&*self.clock)
+    }
+
+    /// Writes a document and updates its metadata.
+    fn save_document<T: Serialize>(&self, obj: &T, id: &str, ttl: Option<Duration>) -> Result<()> {
+        self.schema.check(obj)?;
+        if let Some(retention) = &self.cfg.history {
+            self.archive(id, retention)?;
+        }
+        self.uncache(id);
+        self.write_document(obj, id)?;
+        let prev = if self.cfg.metadata {
+            self.load_meta(id)?
+        } else {
+            None
+        };
+        let now = self.now();
+        let meta = Meta::next(prev.as_ref(), now, self.cfg.metadata, ttl);
+        self.store_meta(id, meta.as_ref())?;
+        if let Some(cache) = &self.cache {
+            let doc = serde_json::to_value(obj).map_err(|err| Error::new(ErrorKind::Other, err))?;
+            cache.put(id, &doc, meta.and_then(|m| m.ttl(now)));
+        }
+        Ok(())
+    }
+
+    fn check_writable(&self) -> Result<()> {
+        if self.cfg.read_only {
+            Err(Error::new(
+                ErrorKind::PermissionDenied,
+                format!("store at {} is read-only", self.path.display()),
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Removes a document from the cache (see `Config::cache`).
+    fn uncache(&self, id: &str) {
+        if let Some(cache) = &self.cache {
+            cache.remove(id);
+        }
+    }
+
+    fn write_document<T: Serialize>(&self, obj: &T, id: &str) -> Result<()> {
+        if self.cfg.single {
+            let json = get_json_from_file(&self.path)?;
+            let object = get_object_from_json(&json)?;
+            let mut x = object.clone();
+            let json =
+                serde_json::to_value(obj).map_err(|err| Error::new(ErrorKind::Other, err))?;
+            x.insert(id.to_owned(), json);
+            self.save_object_to_file(&x, &self.path)
+        } else {
+            self.save_object_to_file(obj, &self.id_to_path(id))
+        }
+    }
+
+    /// Writes (`Some`) or deletes (`None`) several documents in single-file mode
+    /// with one read and one write of the JSON file.
+    ///
+    /// Deleting a document that doesn't exist fails for that item only.
+    fn apply_changes(&self, changes: Vec<(&str, Option<Value>)>) -> Result<Vec<Result<()>>> {
+        let json = get_json_from_file(&self.path)?;
+        let mut object = get_object_from_json(&json)?.clone();
+        let now = self.now();
+        let mut prev_meta = if self.cfg.metadata {
+            self.load_all_meta()?
+        } else {
+            BTreeMap::new()
+        };
+        let mut results = vec![];
+        let mut archived = vec![];
+        let mut trashed = vec![];
+        let mut metas = vec![];
+        for (id, doc) in changes {
+            let prev = if let Some(doc) = doc {
+                let meta = Meta::next(prev_meta.get(id), now, self.cfg.metadata, None);
+                if let Some(meta) = &meta {
+                    prev_meta.insert(id.to_owned(), meta.clone());
+                }
+                metas.push((id, meta));
+                object.insert(id.to_owned(), doc)
+            } else {
+                let Some(prev) = object.remove(id) else {
+                    results.push(Err(Error::new(ErrorKind::NotFound, "no such object")));
+                    continue;
+                };
+                prev_meta.remove(id);
+                metas.push((id, None));
+                if self.cfg.trash {
+                    trashed.push((id, prev.clone()));
+                }
+                Some(prev)
+            };
+            if let (Some(prev), Some(_)) = (prev, &self.cfg.history) {
+                archived.push((id, prev));
+            }
+            results.push(Ok(()));
+        }
+        if let Some(retention) = &self.cfg.history {
+            self.archive_documents(archived, retention)?;
+        }
+        self.trash_documents(trashed)?;
+        self.save_object_to_file(&object, &self.path)?;
+        self.store_all_meta(&metas)?;
+        Ok(results)
+    }
+
+    /// Writes documents buffered in write-behind mode.
+    fn write_pending(&self, docs: &BTreeMap<String, Value>) -> Result<()> {
+        let changes = docs
+            .iter()
+            .map(|(id, doc)| (id.as_str(), Some(doc.clone())))
+            .collect();
+        self.apply_changes(changes)?;
+        Ok(())
+    }
+
+    /// Saves buffered documents that could not be written when the store
+    /// was dropped to a new file in `<stem>.recovery` next to the store.
+    ///
+    /// Like quarantined documents, they are kept in one file that maps IDs
+    /// to documents.
+    fn save_recovery(&self, docs: &BTreeMap<String, Value>) -> Result<PathBuf> {
+        let dir = self.path.with_extension("recovery");
+        create_dir_all(&dir)?;
+        let file_name = dir.join(Uuid::new_v4().to_string()).with_extension("json");
+        self.save_object_to_file(docs, &file_name)?;
+        Ok(file_name)
+    }
+
+    /// Reads a document without checking its expiration.
+    fn read_document(&self, id: &str) -> Result<Value> {
         let json = get_json_from_file(&self.id_to_path(id))?;
-        let object = if self.cfg.single {
+        if self.cfg.single {
             let x = json
                 .get(id)
                 .ok_or_else(|| Error::new(ErrorKind::NotFound, "no such object"))?;
-            x.clone()
+            Ok(x.clone())
         } else {
-            json
-        };
-        decode(object)
+            Ok(json)
+        }
     }
 
-    fn all<T>(&self) -> Result<BTreeMap<String, T>>
-    where
-        for<'de> T: Deserialize<'de>,
-    {
+    fn meta_path(&self, id: &str) -> PathBuf {
         if self.cfg.single {
-            let json = get_json_from_file(&self.id_to_path(""))?;
-            let object = get_object_from_json(&json)?;
-            let mut result = BTreeMap::new();
-            for x in object {
-                let (key, value) = x;
-                if let Ok(r) = decode(value.clone()) {
-                    result.insert(key.clone(), r);
-                }
-            }
-            return Ok(result);
+            self.path.with_extension("meta.json")
+        } else {
+            self.path.join(META_DIR).join(id).with_extension("json")
         }
+    }
 
-        if !metadata(&self.path)?.is_dir() {
-            return Err(Error::new(ErrorKind::NotFound, "invalid path"));
+    fn load_meta(&self, id: &str) -> Result<Option<Meta>> {
+        if self.cfg.single {
+            return Ok(self.load_all_meta()?.remove(id));
+        }
+        match get_json_from_file(&self.meta_path(id)) {
+            Ok(json) => decode(json).map(Some),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
         }
+    }
 
-        let entries = read_dir(&self.path)?
-            .filter_map(|e| {
-                e.and_then(|x| {
-                    x.metadata().and_then(|m| {
-                        if m.is_file() {
-                            path_buf_to_id(&x.path())
-                        } else {
-                            Err(Error::new(ErrorKind::Other, "not a file"))
-                        }
-                    })
-                })
-                .ok()
-            })
-            .filter_map(|id| {
-                self.get(&id)
-                    .map_or_else(|_| None, |x| Some((id.clone(), x)))
-            })
-            .collect::<BTreeMap<String, T>>();
+    fn load_all_meta(&self) -> Result<BTreeMap<String, Meta>> {
+        if self.cfg.single {
+            let path = self.meta_path("");
+            if !path.exists() {
+                return Ok(BTreeMap::new());
+            }
+            return decode(get_json_from_file(&path)?);
+        }
+        let dir = self.path.join(META_DIR);
+        if !dir.is_dir() {
+            return Ok(BTreeMap::new());
+        }
+        let mut result = BTreeMap::new();
+        for entry in read_dir(dir)? {
+            let path = entry?.path();
+            if !is_json_file(&path) {
+                continue;
+            }
+            let Ok(id) = path_buf_to_id(&path) else {
+                continue;
+            };
+            match get_json_from_file(&path).and_then(decode) {
+                Ok(meta) => {
+                    result.insert(id, meta);
+                }
+                Err(err) => log::warn!("Ignoring metadata of '{id}': {err}"),
+            }
+        }
+        Ok(result)
+    }
 
-        Ok(entries)
+    /// Stores (or removes if `meta` is `None`) the metadata of a document.
+    fn store_meta(&self, id: &str, meta: Option<&Meta>) -> Result<()> {
+        self.store_all_meta(&[(id, meta.cloned())])
     }
 
-    fn delete(&self, id: &str) -> Result<()> {
+    /// Stores (or removes if it is `None`) the metadata of several documents.
+    fn store_all_meta(&self, metas: &[(&str, Option<Meta>)]) -> Result<()> {
         if self.cfg.single {
-            let json = get_json_from_file(&self.path)?;
-            let object = get_object_from_json(&json)?;
-            let mut x = object.clone();
-            if x.contains_key(id) {
-                x.remove(id);
-            } else {
-                return Err(Error::new(ErrorKind::NotFound, "no such object"));
+            let path = self.meta_path("");
+            if metas.iter().all(|(_, meta)| meta.is_none()) && !path.exists() {
+                return Ok(());
+            }
+            let mut all = self.load_all_meta()?;
+            let mut changed = false;
+            for (id, meta) in metas {
+                changed |= match meta {
+                    Some(meta) => all.insert((*id).to_owned(), meta.clone()).as_ref() != Some(meta),
+                    None => all.remove(*id).is_some(),
+                };
+            }
+            if changed {
+                self.save_object_to_file(&all, &path)?;
+            }
+            return Ok(());
+        }
+        for (id, meta) in metas {
+            let path = self.meta_path(id);
+            if let Some(meta) = meta {
+                create_dir_all(self.path.join(META_DIR))?;
+                self.save_object_to_file(meta, &path)?;
+            } else if let Err(err) = remove_file(path) {
+                if err.kind() != ErrorKind::NotFound {
+                    return Err(err);
+                }
             }
-            self.save_object_to_file(&x, &self.path)
-        } else {
-            remove_file(self.id_to_path(id))
         }
+        Ok(())
     }
-}
 
-impl FileStore {
     fn id_to_path(&self, id: &str) -> PathBuf {
         if self.cfg.single {
             self.path.clone()
@@ -183,22 +1190,26 @@ impl FileStore {
     fn save_object_to_file<T: Serialize>(&self, obj: &T, file_name: &Path) -> Result<()> {
         let json_string = self.object_to_string(obj)?;
         let mut tmp_filename = file_name.to_path_buf();
-        tmp_filename.set_file_name(Uuid::new_v4().to_string());
-        tmp_filename.set_extension("tmp");
-        let file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(false)
-            .open(file_name)?;
+        let stem = file_name.file_stem().unwrap_or_default().to_string_lossy();
+        tmp_filename.set_file_name(format!("{stem}.{}.{TMP_EXTENSION}", Uuid::new_v4()));
+        // Lock the temporary file as early as possible so that
+        // `remove_stale_tmp_files` never mistakes it for an orphan.
         let mut tmp_file = OpenOptions::new()
             .write(true)
             .create(true)
             .truncate(true)
             .open(&tmp_filename)?;
-        file.lock_exclusive()?;
         tmp_file.lock_exclusive()?;
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(file_name)?;
+        file.lock_exclusive()?;
 
         if let Err(err) = Write::write_all(&mut tmp_file, json_string.as_bytes()) {
+            drop(tmp_file);
+            let _ = remove_file(&tmp_filename);
             Err(err)
         } else {
             FileExt::unlock(&tmp_file)?;
@@ -214,18 +1225,63 @@ impl FileStore {
         FileStore::new_with_cfg(path, Config::default())
     }
 
-    pub fn new_with_cfg<P: AsRef<Path>>(path: P, cfg: Config) -> Result<Self> {
+    #[cfg(test)]
+    fn new_with_cfg<P: AsRef<Path>>(path: P, cfg: Config) -> Result<Self> {
+        FileStore::new_with_clock(path, cfg, Arc::new(crate::meta::SystemClock))
+    }
+
+    pub fn new_with_clock<P: AsRef<Path>>(
+        path: P,
+        cfg: Config,
+        clock: Arc<dyn Clock>,
+    ) -> Result<Self> {
         let mut s = Self {
             path: path.as_ref().to_path_buf(), // TODO: probably change this to take an owned PathBuf parameter
             cfg,
+            clock: Arc::clone(&clock),
+            schema: Schema::default(),
+            cache: None,
+            write_behind: None,
         };
 
+        if cfg.read_only {
+            if cfg.single {
+                s.path = s.path.with_extension("json");
+            }
+            let exists = if cfg.single {
+                s.path.is_file()
+            } else {
+                s.path.is_dir()
+            };
+            if !exists {
+                return Err(Error::new(
+                    ErrorKind::NotFound,
+                    format!("no store found at {}", s.path.display()),
+                ));
+            }
+            if !cfg.single {
+                s.cache = cfg.cache.map(|capacity| Cache::new(capacity, clock));
+            }
+            return Ok(s);
+        }
         if cfg.single {
             s.path = s.path.with_extension("json");
+            for dir in s.tmp_dirs() {
+                remove_stale_tmp_files(&dir, &s.tmp_prefix())?;
+            }
             if !s.path.exists() {
                 let o = Object::new();
                 s.save_object_to_file(&o, &s.path)?;
             }
+            if let Some(wb) = cfg.write_behind {
+                let store = s.clone();
+                let recovery = s.clone();
+                s.write_behind = Some(Arc::new(Buffer::spawn(
+                    wb,
+                    move |docs| store.write_pending(docs),
+                    move |docs| recovery.save_recovery(docs),
+                )));
+            }
             return Ok(s);
         }
         if let Err(err) = create_dir_all(&s.path) {
@@ -233,9 +1289,66 @@ impl FileStore {
                 return Err(err);
             }
         }
+        for dir in s.tmp_dirs() {
+            remove_stale_tmp_files(&dir, &s.tmp_prefix())?;
+        }
+        s.cache = cfg.cache.map(|capacity| Cache::new(capacity, clock));
         Ok(s)
     }
 
+    pub const fn cfg(&self) -> &Config {
+        &self.cfg
+    }
+
+    /// Writes all documents buffered in write-behind mode
+    /// (see `Config::write_behind`).
+    pub fn flush(&self) -> Result<()> {
+        self.write_behind.as_ref().map_or(Ok(()), |b| b.flush())
+    }
+
+    /// Returns the hit and miss counters of the cache (see `Config::cache`).
+    pub fn cache_stats(&self) -> Option<CacheStats> {
+        self.cache.as_ref().map(Cache::stats)
+    }
+
+    /// Replaces all documents with the given ones.
+    ///
+    /// In directory mode every other `*.json` file in the directory is
+    /// deleted, and it fails without writing anything if an ID isn't a
+    /// plain file name (see `check_id_is_file_name`).
+    /// Metadata, history and trash are left untouched.
+    pub fn write_snapshot(&self, docs: &BTreeMap<String, Value>) -> Result<()> {
+        self.check_writable()?;
+        self.flush()?;
+        if self.cfg.single {
+            return self.save_object_to_file(docs, &self.path);
+        }
+        for id in docs.keys() {
+            check_id_is_file_name(id)?;
+        }
+        if let Some(cache) = &self.cache {
+            cache.clear();
+        }
+        let mut written = HashSet::new();
+        for (id, doc) in docs {
+            let path = self.id_to_path(id);
+            self.save_object_to_file(doc, &path)?;
+            written.insert(path);
+        }
+        for entry in read_dir(&self.path)? {
+            let path = entry?.path();
+            if !is_json_file(&path) || !path.is_file() || written.contains(&path) {
+                continue;
+            }
+            if let Err(err) = remove_file(&path) {
+                if err.kind() != ErrorKind::NotFound {
+                    return Err(err);
+                }
+            }
+        }
+        Ok(())
+    }
+
     /// Returns the storage path for the backing JSON store.
     ///
     /// In single-file-mode this will be the JSON file location, otherwise it's
@@ -275,17 +1388,137 @@ fn get_object_from_json(json: &Value) -> Result<&Object> {
         .ok_or_else(|| Error::new(ErrorKind::InvalidData, "invalid file content"))
 }
 
+fn is_json_file(p: &Path) -> bool {
+    p.extension().is_some_and(|ext| ext == "json")
+}
+
+/// Returns `true` if the file name starts with `prefix` and looks like
+/// one of the temporary files created by `save_object_to_file`
+/// (`<stem>.<uuid>.tmp`, named after the file being written).
+fn is_tmp_file(p: &Path, prefix: &str) -> bool {
+    p.extension().is_some_and(|ext| ext == TMP_EXTENSION)
+        && p.file_stem()
+            .and_then(|s| s.to_str())
+            .filter(|s| s.starts_with(prefix))
+            .map(|s| s.rsplit_once('.').map_or(s, |(_, uuid)| uuid))
+            .is_some_and(|s| Uuid::parse_str(s).is_ok())
+}
+
+/// Removes temporary files that were left behind by an interrupted write.
+fn remove_stale_tmp_files(dir: &Path, prefix: &str) -> Result<()> {
+    for path in stale_tmp_files(dir, prefix)? {
+        match remove_file(&path) {
+            Ok(()) => log::warn!("Removed stale temporary file {}", path.display()),
+            Err(err) if err.kind() == ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(())
+}
+
+/// Finds temporary files that were left behind by an interrupted write.
+///
+/// A temporary file that is still locked or that was modified recently
+/// belongs to a write in progress (possibly in another process) and is
+/// left alone.
+fn stale_tmp_files(dir: &Path, prefix: &str) -> Result<Vec<PathBuf>> {
+    let mut stale = vec![];
+    for entry in read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if !is_tmp_file(&path, prefix) {
+            continue;
+        }
+        let Ok(meta) = entry.metadata() else {
+            continue;
+        };
+        let is_recent = meta
+            .modified()
+            .ok()
+            .and_then(|t| t.elapsed().ok())
+            .is_none_or(|age| age < STALE_TMP_FILE_AGE);
+        if !meta.is_file() || is_recent {
+            continue;
+        }
+        let Ok(file) = OpenOptions::new().write(true).open(&path) else {
+            continue;
+        };
+        if file.try_lock_exclusive().is_err() {
+            continue;
+        }
+        FileExt::unlock(&file)?;
+        stale.push(path);
+    }
+    Ok(stale)
+}
+
+/// Reads and parses a JSON file, recording any failure in the `report`.
+fn read_json_file(path: &Path, report: &mut Report) -> Option<Value> {
+    let s = match get_string_from_file(path) {
+        Ok(s) => s,
+        Err(error) => {
+            let path = path.to_path_buf();
+            report.issues.push(Issue::Unreadable { path, error });
+            return None;
+        }
+    };
+    match serde_json::from_str(&s) {
+        Ok(json) => Some(json),
+        Err(err) => {
+            let path = path.to_path_buf();
+            let error = Error::new(ErrorKind::InvalidData, err);
+            report.issues.push(Issue::Malformed { path, error });
+            None
+        }
+    }
+}
+
+/// Returns `dir/file_name`, or a variant of it with a random suffix
+/// if that file already exists.
+fn unused_path(dir: &Path, file_name: PathBuf) -> PathBuf {
+    let path = dir.join(&file_name);
+    if path.exists() {
+        let mut name = file_name.into_os_string();
+        name.push(format!(".{}", Uuid::new_v4()));
+        dir.join(name)
+    } else {
+        path
+    }
+}
+
 fn path_buf_to_id(p: &Path) -> Result<String> {
     p.file_stem()
         .and_then(|n| n.to_os_string().into_string().ok())
         .ok_or_else(|| Error::new(ErrorKind::Other, "invalid id"))
 }
 
+/// Checks that a document with this ID keeps it in directory mode,
+/// i.e. that the ID is a plain file name without an extension.
+pub fn check_id_is_file_name(id: &str) -> Result<()> {
+    let path = Path::new(id).with_extension("json");
+    if path.parent() == Some(Path::new("")) && path_buf_to_id(&path).is_ok_and(|p| p == id) {
+        Ok(())
+    } else {
+        Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("ID '{id}' can't be used as a file name"),
+        ))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use serde_derive::{Deserialize, Serialize};
-    use std::{collections::BTreeMap, fs::File, io::ErrorKind, path::Path, thread};
+    use std::{
+        collections::BTreeMap,
+        fs::File,
+        io::ErrorKind,
+        path::Path,
+        sync::atomic::{AtomicU64, Ordering},
+        thread,
+        time::{SystemTime, UNIX_EPOCH},
+    };
     use tempfile::tempdir;
 
     #[derive(Serialize, Deserialize)]
@@ -306,6 +1539,15 @@ mod tests {
         z: f32,
     }
 
+    #[derive(Debug, Default)]
+    struct ManualClock(AtomicU64);
+
+    impl Clock for ManualClock {
+        fn now(&self) -> SystemTime {
+            UNIX_EPOCH + Duration::from_millis(self.0.load(Ordering::SeqCst))
+        }
+    }
+
     fn write_to_test_file(name: &Path, content: &str) {
         let mut file = File::create(&name).unwrap();
         Write::write_all(&mut file, content.as_bytes()).unwrap();
@@ -401,77 +1643,456 @@ mod tests {
         }
 
         #[test]
-        fn pretty_print_file_content() {
-            let dir = tempdir().unwrap().path().to_path_buf();
+        fn pretty_print_file_content() {
+            let dir = tempdir().unwrap().path().to_path_buf();
+            let mut cfg = Config::default();
+            cfg.pretty = true;
+            let db = FileStore::new_with_cfg(&dir, cfg).unwrap();
+
+            #[derive(Deserialize, Serialize)]
+            struct SubStruct {
+                c: u32,
+            }
+
+            #[derive(Deserialize, Serialize)]
+            struct MyData {
+                a: String,
+                b: SubStruct,
+            }
+
+            let data = MyData {
+                a: "foo".to_string(),
+                b: SubStruct { c: 33 },
+            };
+
+            let id = db.save(&data).unwrap();
+            let mut f = File::open(dir.join(id).with_extension("json")).unwrap();
+            let mut buffer = String::new();
+            f.read_to_string(&mut buffer).unwrap();
+            let expected = "{\n  \"a\": \"foo\",\n  \"b\": {\n    \"c\": 33\n  }\n}";
+            assert_eq!(buffer, expected);
+        }
+
+        #[test]
+        fn get() {
+            let dir = tempdir().unwrap().path().to_path_buf();
+            let db = FileStore::new(&dir).unwrap();
+            let mut file = File::create(dir.join("foo.json")).unwrap();
+            Write::write_all(&mut file, b"{\"z\":9.9}").unwrap();
+            let obj: Z = db.get("foo").unwrap();
+            assert_eq!(obj.z, 9.9);
+        }
+
+        #[test]
+        fn get_non_existent() {
+            let dir = tempdir().unwrap().path().to_path_buf();
+            let db = FileStore::new(&dir).unwrap();
+            let res = db.get::<X>("foobarobject");
+            assert!(res.is_err());
+            assert_eq!(res.err().unwrap().kind(), ErrorKind::NotFound);
+        }
+
+        #[test]
+        fn all() {
+            let dir = tempdir().unwrap().path().to_path_buf();
+            let db = FileStore::new(&dir).unwrap();
+
+            #[derive(Deserialize, Serialize)]
+            struct X {
+                x: u32,
+                y: u32,
+            }
+
+            let mut file = File::create(dir.join("foo.json")).unwrap();
+            Write::write_all(&mut file, b"{\"x\":1, \"y\":0}").unwrap();
+
+            let mut file = File::create(dir.join("bar.json")).unwrap();
+            Write::write_all(&mut file, b"{\"y\":2}").unwrap();
+
+            let all_x: BTreeMap<String, X> = db.all().unwrap();
+            let all_y: BTreeMap<String, Y> = db.all().unwrap();
+            assert_eq!(all_x.get("foo").unwrap().x, 1);
+            assert!(all_x.get("bar").is_none());
+            assert_eq!(all_y.get("bar").unwrap().y, 2);
+        }
+
+        #[test]
+        fn save_with_ttl() {
+            let dir = tempdir().unwrap();
+            let db = FileStore::new(&dir).unwrap();
+            db.save_with_ttl(&X { x: 1 }, "short", Duration::ZERO)
+                .unwrap();
+            db.save_with_ttl(&X { x: 2 }, "long", Duration::from_secs(3600))
+                .unwrap();
+            db.save_with_id(&X { x: 3 }, "forever").unwrap();
+            assert!(dir.path().join(".meta/short.json").exists());
+
+            let res = db.get::<X>("short");
+            assert_eq!(res.err().unwrap().kind(), ErrorKind::NotFound);
+            assert_eq!(db.get::<X>("long").unwrap().x, 2);
+            let all = db.all::<X>().unwrap();
+            assert_eq!(all.keys().collect::<Vec<_>>(), ["forever", "long"]);
+
+            assert_eq!(db.purge_expired().unwrap(), 1);
+            assert!(!dir.path().join("short.json").exists());
+            assert!(!dir.path().join(".meta/short.json").exists());
+
+            db.save_with_id(&X { x: 4 }, "long").unwrap();
+            assert!(!dir.path().join(".meta/long.json").exists());
+        }
+
+        #[test]
+        fn meta() {
+            let dir = tempdir().unwrap();
+            let clock = Arc::new(ManualClock::default());
+            let mut cfg = Config::default();
+            cfg.metadata = true;
+            let db = FileStore::new_with_clock(&dir, cfg, clock.clone()).unwrap();
+            clock.0.store(1_000, Ordering::SeqCst);
+            db.save_with_id(&X { x: 1 }, "foo").unwrap();
+            clock.0.store(2_000, Ordering::SeqCst);
+            db.save_with_id(&X { x: 22 }, "foo").unwrap();
+
+            let meta = db.meta("foo").unwrap();
+            assert_eq!(meta.created_at, Some(UNIX_EPOCH + Duration::from_secs(1)));
+            assert_eq!(meta.updated_at, Some(UNIX_EPOCH + Duration::from_secs(2)));
+            assert_eq!(meta.revision, 2);
+            assert_eq!(meta.size, 8);
+            assert_eq!(meta.expires_at, None);
+
+            db.save_with_ttl(&X { x: 3 }, "foo", Duration::from_secs(1))
+                .unwrap();
+            let meta = db.meta("foo").unwrap();
+            assert_eq!(meta.revision, 3);
+            assert_eq!(meta.expires_at, Some(UNIX_EPOCH + Duration::from_secs(3)));
+            clock.0.store(3_000, Ordering::SeqCst);
+            assert_eq!(db.meta("foo").err().unwrap().kind(), ErrorKind::NotFound);
+            assert!(db.get::<X>("foo").is_err());
+        }
+
+        #[test]
+        fn meta_without_tracking() {
+            let dir = tempdir().unwrap();
+            let db = FileStore::new(&dir).unwrap();
+            db.save_with_id(&X { x: 1 }, "foo").unwrap();
+            assert!(!dir.path().join(".meta").exists());
+            let meta = db.meta("foo").unwrap();
+            assert_eq!(meta.created_at, None);
+            assert_eq!(meta.revision, 0);
+            assert_eq!(meta.size, 7);
+            assert_eq!(db.meta("bar").err().unwrap().kind(), ErrorKind::NotFound);
+        }
+
+        #[test]
+        fn history() {
+            let dir = tempdir().unwrap();
+            let mut cfg = Config::default();
+            cfg.history = Some(Retention {
+                max_versions: Some(2),
+                max_age: None,
+            });
+            let db = FileStore::new_with_cfg(&dir, cfg).unwrap();
+            for x in 1..=4 {
+                db.save_with_id(&X { x }, "foo").unwrap();
+            }
+            let versions = db.history("foo").unwrap();
+            assert_eq!(
+                versions.iter().map(|v| v.number).collect::<Vec<_>>(),
+                [2, 3]
+            );
+            assert!(!dir.path().join(".history/foo/1.json").exists());
+            assert_eq!(db.get_at::<X>("foo", 2).unwrap().x, 2);
+            let res = db.get_at::<X>("foo", 1);
+            assert_eq!(res.err().unwrap().kind(), ErrorKind::NotFound);
+
+            db.delete("foo").unwrap();
+            assert!(db.get::<X>("foo").is_err());
+            db.revert("foo", 4).unwrap();
+            assert_eq!(db.get::<X>("foo").unwrap().x, 4);
+            assert_eq!(db.all::<X>().unwrap().len(), 1);
+        }
+
+        #[test]
+        fn trash() {
+            let dir = tempdir().unwrap();
+            let mut cfg = Config::default();
+            cfg.trash = true;
+            let db = FileStore::new_with_cfg(&dir, cfg).unwrap();
+            db.save_with_id(&X { x: 1 }, "foo").unwrap();
+            db.save_with_id(&X { x: 2 }, "bar").unwrap();
+            db.delete("foo").unwrap();
+            assert!(db.get::<X>("foo").is_err());
+            assert_eq!(db.all::<X>().unwrap().len(), 1);
+            assert!(dir.path().join(".trash/foo.json").exists());
+            let trash = db.list_trash().unwrap();
+            assert_eq!(trash.len(), 1);
+            assert_eq!(trash[0].id, "foo");
+
+            db.save_with_id(&X { x: 3 }, "foo").unwrap();
+            let res = db.restore("foo");
+            assert_eq!(res.err().unwrap().kind(), ErrorKind::AlreadyExists);
+            db.delete("foo").unwrap();
+            db.restore("foo").unwrap();
+            assert_eq!(db.get::<X>("foo").unwrap().x, 3);
+            assert!(db.list_trash().unwrap().is_empty());
+            let res = db.restore("foo");
+            assert_eq!(res.err().unwrap().kind(), ErrorKind::NotFound);
+
+            db.delete("bar").unwrap();
+            assert_eq!(db.empty_trash(Duration::from_secs(3600)).unwrap(), 0);
+            assert_eq!(db.empty_trash(Duration::ZERO).unwrap(), 1);
+            assert!(!dir.path().join(".trash/bar.json").exists());
+        }
+
+        #[test]
+        fn keys() {
+            let dir = tempdir().unwrap();
+            let db = FileStore::new(&dir).unwrap();
+            assert!(db.is_empty().unwrap());
+            db.save_with_id(&X { x: 1 }, "b").unwrap();
+            db.save_with_id(&X { x: 2 }, "a").unwrap();
+            db.save_with_ttl(&X { x: 3 }, "c", Duration::ZERO).unwrap();
+            write_to_test_file(&dir.path().join("d.json"), "not JSON");
+            write_to_test_file(&dir.path().join("e.txt"), "{}");
+            assert_eq!(db.keys().unwrap(), vec!["a", "b", "d"]);
+            assert_eq!(db.count().unwrap(), 3);
+            assert!(!db.is_empty().unwrap());
+            assert!(db.exists("a").unwrap());
+            assert!(!db.exists("c").unwrap());
+            assert!(!db.exists("e").unwrap());
+        }
+
+        #[test]
+        fn scan() {
+            let dir = tempdir().unwrap();
+            let db = FileStore::new(&dir).unwrap();
+            for (x, id) in (0..).zip(["user:b", "user:a", "group:a", "user:c", "users"]) {
+                db.save_with_id(&X { x }, id).unwrap();
+            }
+            db.save_with_ttl(&X { x: 9 }, "user:0", Duration::ZERO)
+                .unwrap();
+            write_to_test_file(&dir.path().join("user:aa.json"), "{}");
+
+            let ids = |res: Vec<(String, X)>| res.into_iter().map(|(id, _)| id).collect::<Vec<_>>();
+            let res = db.scan(&|id| id.starts_with("user:"), ScanOptions::default());
+            assert_eq!(ids(res.unwrap()), vec!["user:a", "user:b", "user:c"]);
+            let opts = ScanOptions {
+                limit: Some(2),
+                reverse: true,
+            };
+            let res = db.scan(&|id| id.starts_with("user:"), opts);
+            assert_eq!(ids(res.unwrap()), vec!["user:c", "user:b"]);
+            let res = db.scan(&|id| ("a".."user:b").contains(&id), ScanOptions::default());
+            assert_eq!(ids(res.unwrap()), vec!["group:a", "user:a"]);
+        }
+
+        #[test]
+        fn paths() {
+            let dir = tempdir().unwrap();
             let mut cfg = Config::default();
-            cfg.pretty = true;
+            cfg.history = Some(Retention::default());
             let db = FileStore::new_with_cfg(&dir, cfg).unwrap();
+            let doc = serde_json::json!({ "settings": { "theme": "dark" } });
+            db.save_with_ttl(&doc, "foo", Duration::from_secs(3600))
+                .unwrap();
+            assert_eq!(
+                db.get_path::<String>("foo", "/settings/theme").unwrap(),
+                "dark"
+            );
+            db.set_path("foo", "/settings/theme", &"light").unwrap();
+            db.set_path("foo", "/settings/size", &12).unwrap();
+            assert_eq!(
+                read_from_test_file(&dir.path().join("foo.json")),
+                r#"{"settings":{"size":12,"theme":"light"}}"#
+            );
+            assert!(db.meta("foo").unwrap().expires_at.is_some());
+            assert_eq!(db.history("foo").unwrap().len(), 2);
+            let res = db.set_path("foo", "/other/size", &12);
+            assert_eq!(res.err().unwrap().kind(), ErrorKind::NotFound);
+            let res = db.set_path("bar", "/size", &12);
+            assert_eq!(res.err().unwrap().kind(), ErrorKind::NotFound);
+            assert!(db.get_path::<u32>("foo", "/settings/theme").is_err());
+        }
 
-            #[derive(Deserialize, Serialize)]
-            struct SubStruct {
-                c: u32,
-            }
-
-            #[derive(Deserialize, Serialize)]
-            struct MyData {
-                a: String,
-                b: SubStruct,
-            }
-
-            let data = MyData {
-                a: "foo".to_string(),
-                b: SubStruct { c: 33 },
-            };
+        #[test]
+        fn cache() {
+            let dir = tempdir().unwrap();
+            let clock = Arc::new(ManualClock::default());
+            let mut cfg = Config::default();
+            cfg.cache = Some(2);
+            let db = FileStore::new_with_clock(&dir, cfg, clock.clone()).unwrap();
+            db.save_with_id(&X { x: 1 }, "a").unwrap();
+            db.save_with_ttl(&X { x: 2 }, "b", Duration::from_secs(1))
+                .unwrap();
+            write_to_test_file(&dir.path().join("a.json"), "{\"x\":9}");
+            assert_eq!(db.get::<X>("a").unwrap().x, 1);
+            assert_eq!(db.get::<X>("b").unwrap().x, 2);
+            let stats = db.cache_stats().unwrap();
+            assert_eq!((stats.hits, stats.misses), (2, 0));
+
+            clock.0.store(1_000, Ordering::SeqCst);
+            assert!(db.get::<X>("b").is_err());
+            db.save_with_id(&X { x: 3 }, "c").unwrap();
+            db.delete("c").unwrap();
+            assert!(db.get::<X>("c").is_err());
+            db.save_with_id(&X { x: 4 }, "d").unwrap();
+            db.save_with_id(&X { x: 5 }, "e").unwrap();
+            assert_eq!(db.get::<X>("a").unwrap().x, 9);
+            let stats = db.cache_stats().unwrap();
+            assert_eq!((stats.hits, stats.misses), (2, 3));
+        }
 
-            let id = db.save(&data).unwrap();
-            let mut f = File::open(dir.join(id).with_extension("json")).unwrap();
-            let mut buffer = String::new();
-            f.read_to_string(&mut buffer).unwrap();
-            let expected = "{\n  \"a\": \"foo\",\n  \"b\": {\n    \"c\": 33\n  }\n}";
-            assert_eq!(buffer, expected);
+        #[test]
+        fn read_only() {
+            let dir = tempdir().unwrap();
+            let mut cfg = Config::default();
+            cfg.read_only = true;
+            let path = dir.path().join("db");
+            let err = FileStore::new_with_cfg(&path, cfg).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::NotFound);
+            assert!(!path.exists());
+
+            FileStore::new(&path)
+                .unwrap()
+                .save_with_id(&X { x: 1 }, "foo")
+                .unwrap();
+            let db = FileStore::new_with_cfg(&path, cfg).unwrap();
+            assert_eq!(db.get::<X>("foo").unwrap().x, 1);
+            assert_eq!(db.keys().unwrap(), ["foo"]);
+            let err = db.save_with_id(&X { x: 2 }, "foo").unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+            assert!(db.delete("foo").is_err());
+            assert!(db.set_path("foo", "/x", &3).is_err());
+            assert!(db.clear().is_err());
+            assert_eq!(db.get::<X>("foo").unwrap().x, 1);
         }
 
         #[test]
-        fn get() {
-            let dir = tempdir().unwrap().path().to_path_buf();
+        fn bulk() {
+            let dir = tempdir().unwrap();
             let db = FileStore::new(&dir).unwrap();
-            let mut file = File::create(dir.join("foo.json")).unwrap();
-            Write::write_all(&mut file, b"{\"z\":9.9}").unwrap();
-            let obj: Z = db.get("foo").unwrap();
-            assert_eq!(obj.z, 9.9);
+            let res = db
+                .save_many(&[("a", X { x: 1 }), ("b", X { x: 2 })])
+                .unwrap();
+            assert_eq!(res.len(), 2);
+            assert_eq!(res[1].as_ref().unwrap(), "b");
+            let res = db.get_many::<X>(&["b", "c"]).unwrap();
+            assert_eq!(res[0].as_ref().unwrap().x, 2);
+            assert_eq!(res[1].as_ref().err().unwrap().kind(), ErrorKind::NotFound);
+            let res = db.delete_many(&["a", "c"]).unwrap();
+            assert!(res[0].is_ok());
+            assert!(res[1].is_err());
+            write_to_test_file(&dir.path().join("notes.txt"), "keep me");
+            assert_eq!(db.clear().unwrap(), 1);
+            assert!(db.all::<X>().unwrap().is_empty());
+            assert!(dir.path().join("notes.txt").exists());
         }
 
         #[test]
-        fn get_non_existent() {
-            let dir = tempdir().unwrap().path().to_path_buf();
+        fn all_with_errors() {
+            let dir = tempdir().unwrap();
             let db = FileStore::new(&dir).unwrap();
-            let res = db.get::<X>("foobarobject");
-            assert!(res.is_err());
-            assert_eq!(res.err().unwrap().kind(), ErrorKind::NotFound);
+            write_to_test_file(&dir.path().join("foo.json"), "{\"x\":1}");
+            write_to_test_file(&dir.path().join("bar.json"), "{\"y\":2}");
+            write_to_test_file(&dir.path().join("baz.json"), "{");
+
+            let (all, errors) = db.all_with_errors::<X>().unwrap();
+            assert_eq!(all.len(), 1);
+            let mut failed = errors.into_iter().map(|(id, _)| id).collect::<Vec<_>>();
+            failed.sort();
+            assert_eq!(failed, ["bar", "baz"]);
+
+            assert_eq!(db.all::<X>().unwrap().len(), 1);
+            assert!(db.all_strict::<X>().is_err());
+            remove_file(dir.path().join("bar.json")).unwrap();
+            remove_file(dir.path().join("baz.json")).unwrap();
+            assert_eq!(db.all_strict::<X>().unwrap().len(), 1);
         }
 
         #[test]
-        fn all() {
+        fn all_ignores_non_json_files() {
             let dir = tempdir().unwrap().path().to_path_buf();
             let db = FileStore::new(&dir).unwrap();
+            write_to_test_file(&dir.join("foo.json"), "{\"x\":1}");
+            write_to_test_file(&dir.join("bar.txt"), "{\"x\":2}");
+            write_to_test_file(
+                &dir.join(Uuid::new_v4().to_string()).with_extension("tmp"),
+                "{\"x\":3}",
+            );
+            let all: BTreeMap<String, X> = db.all().unwrap();
+            assert_eq!(all.len(), 1);
+            assert_eq!(all.get("foo").unwrap().x, 1);
+        }
 
-            #[derive(Deserialize, Serialize)]
-            struct X {
-                x: u32,
-                y: u32,
+        #[test]
+        fn remove_stale_tmp_files_on_open() {
+            let dir = tempdir().unwrap().path().to_path_buf();
+            create_dir_all(&dir).unwrap();
+            let stale = dir.join(Uuid::new_v4().to_string()).with_extension("tmp");
+            let fresh = dir.join(Uuid::new_v4().to_string()).with_extension("tmp");
+            let foreign = dir.join("notes.tmp");
+            for f in [&stale, &fresh, &foreign] {
+                write_to_test_file(f, "{}");
             }
+            let old = std::time::SystemTime::now() - STALE_TMP_FILE_AGE * 2;
+            File::options()
+                .write(true)
+                .open(&stale)
+                .unwrap()
+                .set_modified(old)
+                .unwrap();
+            FileStore::new(&dir).unwrap();
+            assert!(!stale.exists());
+            assert!(fresh.exists());
+            assert!(foreign.exists());
+        }
 
-            let mut file = File::create(dir.join("foo.json")).unwrap();
-            Write::write_all(&mut file, b"{\"x\":1, \"y\":0}").unwrap();
-
-            let mut file = File::create(dir.join("bar.json")).unwrap();
-            Write::write_all(&mut file, b"{\"y\":2}").unwrap();
+        #[test]
+        fn verify() {
+            let dir = tempdir().unwrap();
+            let db = FileStore::new(&dir).unwrap();
+            write_to_test_file(&dir.path().join("ok.json"), "{\"x\":1}");
+            write_to_test_file(&dir.path().join("broken.json"), "{\"x\":");
+            write_to_test_file(&dir.path().join("other.json"), "{\"y\":1}");
+            write_to_test_file(&dir.path().join("a.b.json"), "{\"x\":2}");
+
+            let report = db.verify::<X>().unwrap();
+            assert_eq!(report.issues.len(), 3);
+            assert!(report.issues.iter().any(
+                |i| matches!(i, Issue::Malformed { path, .. } if path.ends_with("broken.json"))
+            ));
+            assert!(report
+                .issues
+                .iter()
+                .any(|i| matches!(i, Issue::InvalidId { path } if path.ends_with("a.b.json"))));
+            assert!(report
+                .issues
+                .iter()
+                .any(|i| matches!(i, Issue::Undecodable { id, .. } if id == "other")));
+
+            let report = db.verify::<Value>().unwrap();
+            assert_eq!(report.issues.len(), 2);
+        }
 
-            let all_x: BTreeMap<String, X> = db.all().unwrap();
-            let all_y: BTreeMap<String, Y> = db.all().unwrap();
-            assert_eq!(all_x.get("foo").unwrap().x, 1);
-            assert!(all_x.get("bar").is_none());
-            assert_eq!(all_y.get("bar").unwrap().y, 2);
+        #[test]
+        fn repair() {
+            let dir = tempdir().unwrap();
+            let db = FileStore::new(&dir).unwrap();
+            write_to_test_file(&dir.path().join("ok.json"), "{\"x\":1}");
+            write_to_test_file(&dir.path().join("broken.json"), "{\"x\":");
+            write_to_test_file(&dir.path().join("other.json"), "{\"y\":1}");
+
+            let report = db.repair::<X>(RepairPolicy::Quarantine).unwrap();
+            assert_eq!(report.issues.len(), 2);
+            assert!(db.verify::<X>().unwrap().is_ok());
+            assert!(dir.path().join(".quarantine/broken.json").exists());
+            assert!(dir.path().join(".quarantine/other.json").exists());
+            assert_eq!(db.get::<X>("ok").unwrap().x, 1);
+
+            write_to_test_file(&dir.path().join("broken.json"), "[");
+            db.repair::<X>(RepairPolicy::Remove).unwrap();
+            assert!(!dir.path().join("broken.json").exists());
+            assert_eq!(db.all::<Value>().unwrap().len(), 1);
         }
 
         #[test]
@@ -498,6 +2119,31 @@ mod tests {
             assert_eq!(res.err().unwrap().kind(), ErrorKind::NotFound);
         }
 
+        #[test]
+        fn single_remove_stale_tmp_files_on_open() {
+            let dir = tempdir().unwrap();
+            let file_name = dir.path().join("test.json");
+            let own = dir.path().join(format!("test.{}.tmp", Uuid::new_v4()));
+            let foreign = dir.path().join(format!("{}.tmp", Uuid::new_v4()));
+            let other = dir.path().join(format!("other.{}.tmp", Uuid::new_v4()));
+            let old = std::time::SystemTime::now() - STALE_TMP_FILE_AGE * 2;
+            for f in [&own, &foreign, &other] {
+                write_to_test_file(f, "{}");
+                File::options()
+                    .write(true)
+                    .open(f)
+                    .unwrap()
+                    .set_modified(old)
+                    .unwrap();
+            }
+            let mut cfg = Config::default();
+            cfg.single = true;
+            FileStore::new_with_cfg(&file_name, cfg).unwrap();
+            assert!(!own.exists());
+            assert!(foreign.exists());
+            assert!(other.exists());
+        }
+
         #[test]
         fn single_new_multi_threaded() {
             let dir = tempdir().unwrap();
@@ -614,6 +2260,392 @@ mod tests {
             assert_eq!(all.get("bar").unwrap().x, 9);
         }
 
+        #[test]
+        fn single_save_with_ttl() {
+            let dir = tempdir().unwrap();
+            let file_name = dir.path().join("test.json");
+            let mut cfg = Config::default();
+            cfg.single = true;
+            let db = FileStore::new_with_cfg(&file_name, cfg).unwrap();
+            db.save_with_ttl(&X { x: 1 }, "short", Duration::ZERO)
+                .unwrap();
+            db.save_with_ttl(&X { x: 2 }, "long", Duration::from_secs(3600))
+                .unwrap();
+            assert!(dir.path().join("test.meta.json").exists());
+
+            assert!(db.get::<X>("short").is_err());
+            assert_eq!(db.get::<X>("long").unwrap().x, 2);
+            assert_eq!(db.all::<X>().unwrap().len(), 1);
+
+            assert_eq!(db.purge_expired().unwrap(), 1);
+            assert_eq!(read_from_test_file(&file_name), "{\"long\":{\"x\":2}}");
+            db.delete("long").unwrap();
+            assert_eq!(
+                read_from_test_file(&dir.path().join("test.meta.json")),
+                "{}"
+            );
+        }
+
+        #[test]
+        fn single_meta() {
+            let dir = tempdir().unwrap();
+            let file_name = dir.path().join("test.json");
+            let clock = Arc::new(ManualClock::default());
+            let mut cfg = Config::default();
+            cfg.single = true;
+            cfg.metadata = true;
+            let db = FileStore::new_with_clock(&file_name, cfg, clock.clone()).unwrap();
+            clock.0.store(5, Ordering::SeqCst);
+            db.save_with_id(&X { x: 1 }, "foo").unwrap();
+            clock.0.store(7, Ordering::SeqCst);
+            db.save_with_id(&X { x: 2 }, "foo").unwrap();
+            db.save_with_id(&X { x: 3 }, "bar").unwrap();
+            assert_eq!(
+                read_from_test_file(&dir.path().join("test.meta.json")),
+                "{\"bar\":{\"created_at\":7,\"updated_at\":7,\"revision\":1},\
+                  \"foo\":{\"created_at\":5,\"updated_at\":7,\"revision\":2}}"
+            );
+            let meta = db.meta("foo").unwrap();
+            assert_eq!(meta.created_at, Some(UNIX_EPOCH + Duration::from_millis(5)));
+            assert_eq!(meta.revision, 2);
+            assert_eq!(meta.size, 7);
+            db.delete("foo").unwrap();
+            assert!(db.meta("foo").is_err());
+        }
+
+        #[test]
+        fn single_history() {
+            let dir = tempdir().unwrap();
+            let file_name = dir.path().join("test.json");
+            let mut cfg = Config::default();
+            cfg.single = true;
+            cfg.history = Some(Retention::default());
+            let db = FileStore::new_with_cfg(&file_name, cfg).unwrap();
+            db.save_with_id(&X { x: 1 }, "foo").unwrap();
+            db.save_with_id(&X { x: 2 }, "foo").unwrap();
+            db.save_with_id(&X { x: 3 }, "bar").unwrap();
+            assert!(db.history("bar").unwrap().is_empty());
+            assert_eq!(db.history("foo").unwrap().len(), 1);
+            db.revert("foo", 1).unwrap();
+            assert_eq!(db.get::<X>("foo").unwrap().x, 1);
+            assert_eq!(db.get_at::<X>("foo", 2).unwrap().x, 2);
+            assert_eq!(
+                read_from_test_file(&file_name),
+                "{\"bar\":{\"x\":3},\"foo\":{\"x\":1}}"
+            );
+        }
+
+        #[test]
+        fn single_keys() {
+            let dir = tempdir().unwrap();
+            let file_name = dir.path().join("test.json");
+            let mut cfg = Config::default();
+            cfg.single = true;
+            let db = FileStore::new_with_cfg(&file_name, cfg).unwrap();
+            assert!(db.is_empty().unwrap());
+            db.save_with_id(&X { x: 1 }, "b").unwrap();
+            db.save_with_id(&X { x: 2 }, "a").unwrap();
+            db.save_with_ttl(&X { x: 3 }, "c", Duration::ZERO).unwrap();
+            assert_eq!(db.keys().unwrap(), vec!["a", "b"]);
+            assert_eq!(db.count().unwrap(), 2);
+            assert!(db.exists("b").unwrap());
+            assert!(!db.exists("c").unwrap());
+            assert!(!db.exists("d").unwrap());
+        }
+
+        #[test]
+        fn single_scan() {
+            let dir = tempdir().unwrap();
+            let file_name = dir.path().join("test.json");
+            let mut cfg = Config::default();
+            cfg.single = true;
+            let db = FileStore::new_with_cfg(&file_name, cfg).unwrap();
+            write_to_test_file(
+                &file_name,
+                r#"{"b":{"x":1},"a":{"x":2},"ab":{"y":0},"ac":{"x":3},"c":{"x":4}}"#,
+            );
+            let opts = ScanOptions {
+                limit: Some(1),
+                reverse: true,
+            };
+            let res = db.scan::<X>(&|id| id.starts_with('a'), opts).unwrap();
+            assert_eq!(res.len(), 1);
+            assert_eq!(res[0].0, "ac");
+            let res = db
+                .scan::<X>(&|id| id.starts_with('a'), ScanOptions::default())
+                .unwrap();
+            assert_eq!(res.len(), 2);
+            assert_eq!(res[1].1.x, 3);
+        }
+
+        #[test]
+        fn single_paths() {
+            let dir = tempdir().unwrap();
+            let file_name = dir.path().join("test.json");
+            let mut cfg = Config::default();
+            cfg.single = true;
+            let db = FileStore::new_with_cfg(&file_name, cfg).unwrap();
+            db.save_with_id(&serde_json::json!({ "list": [1] }), "foo")
+                .unwrap();
+            db.save_with_id(&X { x: 1 }, "bar").unwrap();
+            db.set_path("foo", "/list/-", &2).unwrap();
+            db.set_path("bar", "/x", &3).unwrap();
+            assert_eq!(db.get_path::<u32>("foo", "/list/1").unwrap(), 2);
+            assert_eq!(
+                read_from_test_file(&file_name),
+                r#"{"bar":{"x":3},"foo":{"list":[1,2]}}"#
+            );
+        }
+
+        #[test]
+        fn single_bulk() {
+            let dir = tempdir().unwrap();
+            let file_name = dir.path().join("test.json");
+            let mut cfg = Config::default();
+            cfg.single = true;
+            cfg.metadata = true;
+            cfg.history = Some(Retention::default());
+            cfg.trash = true;
+            let db = FileStore::new_with_cfg(&file_name, cfg).unwrap();
+            db.save_many(&[("a", X { x: 1 }), ("b", X { x: 2 }), ("a", X { x: 3 })])
+                .unwrap();
+            assert_eq!(
+                read_from_test_file(&file_name),
+                "{\"a\":{\"x\":3},\"b\":{\"x\":2}}"
+            );
+            assert_eq!(db.meta("a").unwrap().revision, 2);
+            assert_eq!(db.get_at::<X>("a", 1).unwrap().x, 1);
+
+            let res = db.get_many::<X>(&["a", "c"]).unwrap();
+            assert_eq!(res[0].as_ref().unwrap().x, 3);
+            assert!(res[1].is_err());
+
+            let res = db.delete_many(&["b", "b", "c"]).unwrap();
+            assert!(res[0].is_ok());
+            assert_eq!(res[1].as_ref().err().unwrap().kind(), ErrorKind::NotFound);
+            assert!(res[2].is_err());
+            assert_eq!(read_from_test_file(&file_name), "{\"a\":{\"x\":3}}");
+            assert!(db.meta("b").is_err());
+            assert_eq!(db.list_trash().unwrap()[0].id, "b");
+
+            assert_eq!(db.clear().unwrap(), 1);
+            assert_eq!(read_from_test_file(&file_name), "{}");
+            assert_eq!(db.list_trash().unwrap().len(), 2);
+            db.restore("a").unwrap();
+            assert_eq!(db.get::<X>("a").unwrap().x, 3);
+        }
+
+        #[test]
+        fn single_write_behind() {
+            let dir = tempdir().unwrap();
+            let file_name = dir.path().join("test.json");
+            let mut cfg = Config::default();
+            cfg.single = true;
+            cfg.write_behind = Some(WriteBehind {
+                delay: Duration::from_secs(3600),
+                max_pending: 3,
+            });
+            let db = FileStore::new_with_cfg(&file_name, cfg).unwrap();
+            db.save_with_id(&X { x: 1 }, "a").unwrap();
+            db.save_with_id(&X { x: 2 }, "b").unwrap();
+            assert_eq!(read_from_test_file(&file_name), "{}");
+            assert_eq!(db.get::<X>("a").unwrap().x, 1);
+            assert!(db.exists("b").unwrap());
+            assert_eq!(db.keys().unwrap(), ["a", "b"]);
+            assert_eq!(
+                read_from_test_file(&file_name),
+                "{\"a\":{\"x\":1},\"b\":{\"x\":2}}"
+            );
+
+            db.save_with_id(&X { x: 3 }, "c").unwrap();
+            db.save_with_id(&X { x: 4 }, "d").unwrap();
+            assert_eq!(db.all::<X>().unwrap().len(), 4);
+            db.save_with_id(&X { x: 5 }, "e").unwrap();
+            db.save_with_id(&X { x: 6 }, "f").unwrap();
+            db.save_with_id(&X { x: 7 }, "g").unwrap();
+            assert!(read_from_test_file(&file_name).contains("\"g\""));
+
+            db.save_with_id(&X { x: 8 }, "h").unwrap();
+            remove_file(&file_name).unwrap();
+            create_dir_all(&file_name).unwrap();
+            assert!(db.flush().is_err());
+            std::fs::remove_dir(&file_name).unwrap();
+            write_to_test_file(&file_name, "{}");
+            db.save_with_id(&X { x: 9 }, "i").unwrap();
+            drop(db);
+            assert_eq!(
+                read_from_test_file(&file_name),
+                "{\"h\":{\"x\":8},\"i\":{\"x\":9}}"
+            );
+        }
+
+        #[test]
+        fn single_write_behind_max_pending_error() {
+            let dir = tempdir().unwrap();
+            let file_name = dir.path().join("test.json");
+            let mut cfg = Config::default();
+            cfg.single = true;
+            cfg.write_behind = Some(WriteBehind {
+                delay: Duration::from_secs(3600),
+                max_pending: 2,
+            });
+            let db = FileStore::new_with_cfg(&file_name, cfg).unwrap();
+            db.save_with_id(&X { x: 1 }, "a").unwrap();
+            remove_file(&file_name).unwrap();
+            create_dir_all(&file_name).unwrap();
+            db.save_with_id(&X { x: 2 }, "b").unwrap();
+            assert_eq!(db.get::<X>("b").unwrap().x, 2);
+            assert!(db.flush().is_err());
+            std::fs::remove_dir(&file_name).unwrap();
+            write_to_test_file(&file_name, "{}");
+            db.flush().unwrap();
+            assert_eq!(
+                read_from_test_file(&file_name),
+                "{\"a\":{\"x\":1},\"b\":{\"x\":2}}"
+            );
+        }
+
+        #[test]
+        fn single_write_behind_recovery() {
+            let dir = tempdir().unwrap();
+            let file_name = dir.path().join("test.json");
+            let mut cfg = Config::default();
+            cfg.single = true;
+            cfg.write_behind = Some(WriteBehind::default());
+            let db = FileStore::new_with_cfg(&file_name, cfg).unwrap();
+            db.save_with_id(&X { x: 1 }, "a").unwrap();
+            db.save_with_id(&X { x: 2 }, "../b").unwrap();
+            remove_file(&file_name).unwrap();
+            create_dir_all(&file_name).unwrap();
+            drop(db);
+            let recovered = read_dir(dir.path().join("test.recovery"))
+                .unwrap()
+                .map(|e| e.unwrap().path())
+                .collect::<Vec<_>>();
+            assert_eq!(recovered.len(), 1);
+            assert_eq!(
+                read_from_test_file(&recovered[0]),
+                "{\"../b\":{\"x\":2},\"a\":{\"x\":1}}"
+            );
+        }
+
+        #[test]
+        fn single_write_behind_delay() {
+            let dir = tempdir().unwrap();
+            let file_name = dir.path().join("test.json");
+            let mut cfg = Config::default();
+            cfg.single = true;
+            cfg.write_behind = Some(WriteBehind {
+                delay: Duration::from_millis(10),
+                max_pending: 100,
+            });
+            let db = FileStore::new_with_cfg(&file_name, cfg).unwrap();
+            db.save_with_id(&X { x: 1 }, "a").unwrap();
+            thread::sleep(Duration::from_millis(500));
+            assert_eq!(read_from_test_file(&file_name), "{\"a\":{\"x\":1}}");
+        }
+
+        #[test]
+        fn single_read_only() {
+            let dir = tempdir().unwrap();
+            let file_name = dir.path().join("test.json");
+            let mut cfg = Config::default();
+            cfg.single = true;
+            cfg.read_only = true;
+            assert!(FileStore::new_with_cfg(&file_name, cfg).is_err());
+            assert!(!file_name.exists());
+
+            write_to_test_file(&file_name, "{\"foo\":{\"x\":1}}");
+            let db = FileStore::new_with_cfg(&file_name, cfg).unwrap();
+            assert_eq!(db.get::<X>("foo").unwrap().x, 1);
+            let err = db.save(&X { x: 2 }).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+            assert!(db.delete_many(&["foo"]).is_err());
+            assert_eq!(read_from_test_file(&file_name), "{\"foo\":{\"x\":1}}");
+        }
+
+        #[test]
+        fn single_trash() {
+            let dir = tempdir().unwrap();
+            let file_name = dir.path().join("test.json");
+            let mut cfg = Config::default();
+            cfg.single = true;
+            cfg.trash = true;
+            let db = FileStore::new_with_cfg(&file_name, cfg).unwrap();
+            db.save_with_id(&X { x: 1 }, "foo").unwrap();
+            db.delete("foo").unwrap();
+            assert_eq!(read_from_test_file(&file_name), "{}");
+            assert_eq!(db.list_trash().unwrap()[0].id, "foo");
+            db.restore("foo").unwrap();
+            assert_eq!(read_from_test_file(&file_name), "{\"foo\":{\"x\":1}}");
+            assert_eq!(
+                read_from_test_file(&dir.path().join("test.trash.json")),
+                "{}"
+            );
+            db.delete("foo").unwrap();
+            assert_eq!(db.empty_trash(Duration::ZERO).unwrap(), 1);
+            assert!(db.restore("foo").is_err());
+
+            db.save_with_id(&X { x: 2 }, "k").unwrap();
+            db.delete("k").unwrap();
+            db.save_with_ttl(&X { x: 3 }, "k", Duration::ZERO).unwrap();
+            assert!(!db.exists("k").unwrap());
+            db.restore("k").unwrap();
+            assert_eq!(db.get::<X>("k").unwrap().x, 2);
+        }
+
+        #[test]
+        fn single_all_with_errors() {
+            let dir = tempdir().unwrap();
+            let file_name = dir.path().join("test.json");
+            let mut cfg = Config::default();
+            cfg.single = true;
+            let db = FileStore::new_with_cfg(&file_name, cfg).unwrap();
+            write_to_test_file(&file_name, "{\"foo\":{\"x\":8},\"bar\":{\"y\":9}}");
+            let (all, errors) = db.all_with_errors::<X>().unwrap();
+            assert_eq!(all.get("foo").unwrap().x, 8);
+            assert_eq!(errors.len(), 1);
+            assert_eq!(errors[0].0, "bar");
+            let err = db.all_strict::<X>().err().unwrap();
+            assert!(err.to_string().contains("bar"));
+        }
+
+        #[test]
+        fn single_verify_and_repair() {
+            let dir = tempdir().unwrap();
+            let file_name = dir.path().join("test.json");
+            let mut cfg = Config::default();
+            cfg.single = true;
+            let db = FileStore::new_with_cfg(&file_name, cfg).unwrap();
+            write_to_test_file(
+                &file_name,
+                "{\"foo\":{\"x\":8},\"../bar\":{\"y\":9},\"a.b\":{\"y\":0}}",
+            );
+
+            let report = db.verify::<X>().unwrap();
+            assert_eq!(report.issues.len(), 2);
+            assert!(matches!(&report.issues[0], Issue::Undecodable { id, .. } if id == "../bar"));
+
+            db.repair::<X>(RepairPolicy::Quarantine).unwrap();
+            assert_eq!(read_from_test_file(&file_name), "{\"foo\":{\"x\":8}}");
+            let quarantined = read_dir(dir.path().join("test.quarantine"))
+                .unwrap()
+                .map(|e| e.unwrap().path())
+                .collect::<Vec<_>>();
+            assert_eq!(quarantined.len(), 1);
+            assert_eq!(
+                read_from_test_file(&quarantined[0]),
+                "{\"../bar\":{\"y\":9},\"a.b\":{\"y\":0}}"
+            );
+            assert!(!dir.path().join("bar.json").exists());
+
+            write_to_test_file(&file_name, "[1,2]");
+            let report = db.verify::<X>().unwrap();
+            assert!(matches!(&report.issues[0], Issue::NotAnObject { .. }));
+            db.repair::<X>(RepairPolicy::Remove).unwrap();
+            assert_eq!(read_from_test_file(&file_name), "{}");
+        }
+
         #[test]
         fn single_delete() {
             let dir = tempdir().unwrap();
@@ -0,0 +1,100 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Defines how many prior versions of a document are kept
+/// if history mode is enabled (see `Config::history`).
+///
+/// The default keeps all versions forever.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Retention {
+    /// Keep at most this number of versions per document.
+    pub max_versions: Option<usize>,
+    /// Drop versions that were archived longer ago than this.
+    pub max_age: Option<Duration>,
+}
+
+/// A prior version of a document (see `Store::history`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Version {
+    /// The number that can be passed to `Store::get_at` and `Store::revert`.
+    pub number: u64,
+    /// The time this version was replaced or deleted.
+    pub archived_at: SystemTime,
+}
+
+/// A prior version of a document as it is stored.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Archived {
+    pub version: u64,
+    /// Milliseconds since the UNIX epoch.
+    pub archived_at: u64,
+    pub doc: Value,
+}
+
+impl Archived {
+    pub fn to_version(&self) -> Version {
+        Version {
+            number: self.version,
+            archived_at: UNIX_EPOCH + Duration::from_millis(self.archived_at),
+        }
+    }
+}
+
+impl Retention {
+    /// Returns the versions (sorted by version number) that should be dropped.
+    pub(crate) fn expired(&self, versions: &[(u64, u64)], now: u64) -> Vec<u64> {
+        let mut versions = versions.to_vec();
+        versions.sort_unstable();
+        let surplus = self
+            .max_versions
+            .map_or(0, |max| versions.len().saturating_sub(max));
+        let min_time = self.max_age.map_or(0, |age| {
+            now.saturating_sub(u64::try_from(age.as_millis()).unwrap_or(u64::MAX))
+        });
+        versions
+            .iter()
+            .enumerate()
+            .filter(|(i, (_, archived_at))| *i < surplus || *archived_at < min_time)
+            .map(|(_, (version, _))| *version)
+            .collect()
+    }
+}
+
+/// Appends the document as a new version to `history` and applies the retention policy.
+pub fn archive(history: &mut Vec<Archived>, doc: Value, now: u64, retention: &Retention) {
+    let version = history.iter().map(|a| a.version).max().unwrap_or(0) + 1;
+    history.push(Archived {
+        version,
+        archived_at: now,
+        doc,
+    });
+    let versions = history
+        .iter()
+        .map(|a| (a.version, a.archived_at))
+        .collect::<Vec<_>>();
+    let expired = retention.expired(&versions, now);
+    history.retain(|a| !expired.contains(&a.version));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retention() {
+        let versions = [(3, 300), (1, 100), (2, 200)];
+        assert!(Retention::default().expired(&versions, 1_000).is_empty());
+        let keep_two = Retention {
+            max_versions: Some(2),
+            max_age: None,
+        };
+        assert_eq!(keep_two.expired(&versions, 1_000), [1]);
+        let max_age = Retention {
+            max_versions: None,
+            max_age: Some(Duration::from_millis(150)),
+        };
+        assert_eq!(max_age.expired(&versions, 400), [1, 2]);
+    }
+}
@@ -1,6 +1,27 @@
-use std::{collections::BTreeMap, io::Result};
+use std::{
+    collections::BTreeMap,
+    io::{Error, ErrorKind, Result},
+    time::Duration,
+};
 
+use json_patch::Patch;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    history::Version,
+    meta::Metadata,
+    pointer,
+    scan::ScanOptions,
+    trash::TrashEntry,
+    verify::{RepairPolicy, Report},
+};
+
+#[cfg(feature = "jsonschema")]
+use crate::schema::{Schema, ValidationError};
+
+/// Successfully decoded documents and the IDs and errors of those that failed.
+pub type Decoded<T> = (BTreeMap<String, T>, Vec<(String, Error)>);
 
 pub trait JsonStore: Send + Sync {
     fn save<T>(&self, obj: &T) -> Result<String>
@@ -9,11 +30,140 @@ pub trait JsonStore: Send + Sync {
     fn save_with_id<T>(&self, obj: &T, id: &str) -> Result<String>
     where
         for<'de> T: Serialize + Deserialize<'de>;
+    fn save_with_ttl<T>(&self, obj: &T, id: &str, ttl: Duration) -> Result<String>
+    where
+        for<'de> T: Serialize + Deserialize<'de>;
+    fn save_many<T>(&self, docs: &[(&str, T)]) -> Result<Vec<Result<String>>>
+    where
+        for<'de> T: Serialize + Deserialize<'de>,
+    {
+        Ok(docs
+            .iter()
+            .map(|(id, obj)| self.save_with_id(obj, id))
+            .collect())
+    }
+    fn save_value(&self, doc: &Value, id: &str) -> Result<String> {
+        self.save_with_id(doc, id)
+    }
     fn get<T>(&self, id: &str) -> Result<T>
     where
         for<'de> T: Deserialize<'de>;
+    fn get_value(&self, id: &str) -> Result<Value> {
+        self.get(id)
+    }
+    fn get_many<T>(&self, ids: &[&str]) -> Result<Vec<Result<T>>>
+    where
+        for<'de> T: Deserialize<'de>,
+    {
+        Ok(ids.iter().map(|id| self.get(id)).collect())
+    }
+    fn exists(&self, id: &str) -> Result<bool>;
+    fn keys(&self) -> Result<Vec<String>>;
+    fn count(&self) -> Result<usize> {
+        Ok(self.keys()?.len())
+    }
+    fn is_empty(&self) -> Result<bool> {
+        Ok(self.count()? == 0)
+    }
+    fn scan<T>(&self, filter: &dyn Fn(&str) -> bool, opts: ScanOptions) -> Result<Vec<(String, T)>>
+    where
+        for<'de> T: Deserialize<'de>;
+    fn get_path<T>(&self, id: &str, pointer: &str) -> Result<T>
+    where
+        for<'de> T: Deserialize<'de>,
+    {
+        let doc = self.get::<Value>(id)?;
+        T::deserialize(pointer::get(&doc, pointer)?)
+            .map_err(|err| Error::new(ErrorKind::Other, err))
+    }
+    fn set_path<V>(&self, id: &str, pointer: &str, value: &V) -> Result<()>
+    where
+        V: Serialize,
+    {
+        let value = serde_json::to_value(value).map_err(|err| Error::new(ErrorKind::Other, err))?;
+        self.update(id, |doc| pointer::set(doc, pointer, value))
+    }
+    fn patch<P>(&self, id: &str, merge_patch: &P) -> Result<()>
+    where
+        P: Serialize,
+    {
+        let merge_patch =
+            serde_json::to_value(merge_patch).map_err(|err| Error::new(ErrorKind::Other, err))?;
+        self.update(id, |doc| {
+            json_patch::merge(doc, &merge_patch);
+            Ok(())
+        })
+    }
+    fn apply_patch(&self, id: &str, patch: &Patch) -> Result<()> {
+        self.update(id, |doc| {
+            json_patch::patch(doc, patch).map_err(|err| Error::new(ErrorKind::InvalidInput, err))
+        })
+    }
+    #[cfg(feature = "jsonschema")]
+    fn schema(&self) -> &Schema;
+    #[cfg(feature = "jsonschema")]
+    fn validate_all(&self) -> Result<BTreeMap<String, ValidationError>> {
+        let mut invalid = BTreeMap::new();
+        for (id, doc) in self.all::<Value>()? {
+            if let Err(err) = self.schema().validate(&doc) {
+                invalid.insert(id, err);
+            }
+        }
+        Ok(invalid)
+    }
+    /// Atomically modifies a stored document, keeping its expiration.
+    fn update<F>(&self, id: &str, f: F) -> Result<()>
+    where
+        F: FnOnce(&mut Value) -> Result<()>;
+    fn meta(&self, id: &str) -> Result<Metadata>;
+    fn history(&self, id: &str) -> Result<Vec<Version>>;
+    fn get_at<T>(&self, id: &str, version: u64) -> Result<T>
+    where
+        for<'de> T: Deserialize<'de>;
+    fn revert(&self, id: &str, version: u64) -> Result<()> {
+        let doc = self.get_at::<Value>(id, version)?;
+        self.save_with_id(&doc, id)?;
+        Ok(())
+    }
     fn all<T>(&self) -> Result<BTreeMap<String, T>>
+    where
+        for<'de> T: Deserialize<'de>,
+    {
+        let (result, errors) = self.all_with_errors()?;
+        for (id, err) in errors {
+            log::warn!("Skipping document '{id}': {err}");
+        }
+        Ok(result)
+    }
+    fn all_values(&self) -> Result<BTreeMap<String, Value>> {
+        self.all()
+    }
+    fn all_strict<T>(&self) -> Result<BTreeMap<String, T>>
+    where
+        for<'de> T: Deserialize<'de>,
+    {
+        let (result, errors) = self.all_with_errors()?;
+        if let Some((id, err)) = errors.into_iter().next() {
+            return Err(Error::new(err.kind(), format!("document '{id}': {err}")));
+        }
+        Ok(result)
+    }
+    fn all_with_errors<T>(&self) -> Result<Decoded<T>>
     where
         for<'de> T: Deserialize<'de>;
     fn delete(&self, id: &str) -> Result<()>;
+    fn delete_many(&self, ids: &[&str]) -> Result<Vec<Result<()>>> {
+        Ok(ids.iter().map(|id| self.delete(id)).collect())
+    }
+    fn clear(&self) -> Result<usize>;
+    fn restore(&self, id: &str) -> Result<()>;
+    fn list_trash(&self) -> Result<Vec<TrashEntry>>;
+    fn empty_trash(&self, older_than: Duration) -> Result<usize>;
+    fn purge_expired(&self) -> Result<usize>;
+    fn verify<T>(&self) -> Result<Report>
+    where
+        for<'de> T: Deserialize<'de>;
+    fn repair<T>(&self, policy: RepairPolicy) -> Result<Report>
+    where
+        for<'de> T: Deserialize<'de>;
 }
@@ -46,24 +46,71 @@
 //! ```rust,no_run
 //! let db = jfs::Store::new(jfs::IN_MEMORY).unwrap();
 //! ```
+//!
+//! With the `tokio` feature enabled, `AsyncStore` offers the same operations
+//! without blocking the async executor.
+//!
+//! With the `jsonschema` feature enabled, documents can be validated against
+//! a JSON Schema before they're written (see `Store::set_schema`).
 
 use std::{
     collections::BTreeMap,
-    io::Result,
+    io::{Error, ErrorKind, Result},
+    ops::RangeBounds,
     path::{Path, PathBuf},
     sync::Arc,
+    time::Duration,
 };
 
 use parking_lot::RwLock;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
+#[cfg(feature = "tokio")]
+mod async_store;
+mod autosave;
+mod cache;
+mod evict;
 mod file_store;
+mod history;
 mod json_store;
 mod memory_store;
+mod meta;
+mod pointer;
+mod scan;
+mod schema;
+mod sweeper;
+mod trash;
+mod verify;
+mod write_behind;
+
+use self::{
+    file_store::{check_id_is_file_name, FileStore},
+    json_store::{Decoded, JsonStore},
+    memory_store::MemoryStore,
+};
+
+pub use self::{
+    autosave::Autosave,
+    cache::CacheStats,
+    evict::Eviction,
+    file_store::Config,
+    history::{Retention, Version},
+    meta::{Clock, Metadata, SystemClock},
+    scan::{Page, ScanOptions},
+    sweeper::Sweeper,
+    trash::TrashEntry,
+    verify::{Issue, RepairPolicy, Report},
+    write_behind::WriteBehind,
+};
 
-use self::{file_store::FileStore, json_store::JsonStore, memory_store::MemoryStore};
+pub use json_patch::Patch as JsonPatch;
 
-pub use self::file_store::Config;
+#[cfg(feature = "jsonschema")]
+pub use self::schema::{ValidationError, Violation};
+
+#[cfg(feature = "tokio")]
+pub use self::async_store::AsyncStore;
 
 #[derive(Debug, Clone)]
 pub struct Store(StoreType);
@@ -71,9 +118,15 @@ pub struct Store(StoreType);
 #[derive(Debug, Clone)]
 enum StoreType {
     File(Arc<RwLock<FileStore>>, PathBuf),
-    Memory(MemoryStore),
+    Memory(MemoryStore, PathBuf),
 }
 
+/// The path of a `Store` that lives in memory.
+///
+/// Every `Store` opened with this path is a new, empty store.
+/// Append a name (e.g. `"::memory::tenant-a"`) to open a store that is
+/// shared by everyone in the process who opens the same name,
+/// until it's dropped with `Store::drop_named`.
 pub const IN_MEMORY: &str = "::memory::";
 
 impl Store {
@@ -91,20 +144,75 @@ impl Store {
     /// Opens a `Store` against the specified path with the given configuration
     ///
     /// If the `Store` already exists, it will be opened, otherwise this has the side-effect of creating the new `Store`
-    ///  and the backing directories and files.
+    ///  and the backing directories and files (unless `Config::read_only` is set).
     ///
     /// # Arguments
     ///
     /// * `path` - path to the db directory of JSON documents, if configured for single db mode then `.json` will be used as the extension (replacing any existing extension)
     /// * `cfg` - configuration for the DB instance
     pub fn new_with_cfg<P: AsRef<Path>>(path: P, cfg: Config) -> Result<Self> {
-        if path.as_ref() == Path::new(IN_MEMORY) {
-            Ok(Self(StoreType::Memory(MemoryStore::default())))
+        Self::new_with_clock(path, cfg, Arc::new(SystemClock))
+    }
+
+    /// Opens a `Store` like `new_with_cfg(..)` that takes all timestamps
+    /// (see `meta` and `save_with_ttl`) from the given `clock`.
+    pub fn new_with_clock<P: AsRef<Path>>(
+        path: P,
+        cfg: Config,
+        clock: Arc<dyn Clock>,
+    ) -> Result<Self> {
+        match memory_name(path.as_ref()) {
+            Some("") => Ok(Self(StoreType::Memory(
+                MemoryStore::new(cfg, clock),
+                PathBuf::from(IN_MEMORY),
+            ))),
+            Some(name) => Ok(Self(StoreType::Memory(
+                MemoryStore::named(name, cfg, clock),
+                path.as_ref().to_path_buf(),
+            ))),
+            None => {
+                let s = FileStore::new_with_clock(path, cfg, clock)?;
+                let p = s.path().to_path_buf();
+                Ok(Self(StoreType::File(Arc::new(RwLock::new(s)), p)))
+            }
+        }
+    }
+
+    /// Removes a named in-memory store (see `IN_MEMORY`) from the
+    /// process-wide registry and returns `true` if it existed.
+    ///
+    /// Existing handles keep working, but opening the name again
+    /// creates a new, empty store.
+    pub fn drop_named<P: AsRef<Path>>(path: P) -> bool {
+        match memory_name(path.as_ref()) {
+            Some("") | None => false,
+            Some(name) => MemoryStore::drop_named(name),
+        }
+    }
+
+    /// Loads all documents stored at `path` into a new in-memory store.
+    ///
+    /// The documents are read like `new_with_cfg(path, cfg)` would do,
+    /// documents that can't be read are skipped.
+    /// Use `persist_to` to write them back to disk.
+    pub fn load_into_memory<P: AsRef<Path>>(path: P, cfg: Config) -> Result<Self> {
+        let path = if cfg.single {
+            path.as_ref().with_extension("json")
         } else {
-            let s = FileStore::new_with_cfg(path, cfg)?;
-            let p = s.path().to_path_buf();
-            Ok(Self(StoreType::File(Arc::new(RwLock::new(s)), p)))
+            path.as_ref().to_path_buf()
+        };
+        if !path.exists() {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                format!("no store found at {}", path.display()),
+            ));
+        }
+        let docs = FileStore::new_with_clock(&path, cfg, Arc::new(SystemClock))?.all_values()?;
+        let store = MemoryStore::new(cfg, Arc::new(SystemClock));
+        for (id, doc) in &docs {
+            store.save_value(doc, id)?;
         }
+        Ok(Self(StoreType::Memory(store, PathBuf::from(IN_MEMORY))))
     }
 
     /// Returns the storage path for the backing JSON store.
@@ -114,18 +222,115 @@ impl Store {
     #[must_use]
     pub fn path(&self) -> &Path {
         match &self.0 {
-            StoreType::File(_, p) => p,
-            StoreType::Memory(_) => Path::new(IN_MEMORY),
+            StoreType::File(_, p) | StoreType::Memory(_, p) => p,
         }
     }
 
+    fn cfg(&self) -> Config {
+        match &self.0 {
+            StoreType::File(f, _) => *f.read().cfg(),
+            StoreType::Memory(m, _) => *m.cfg(),
+        }
+    }
+
+    /// Writes all documents that are buffered in write-behind mode
+    /// (see `Config::write_behind`).
+    ///
+    /// If writing fails, the documents stay buffered and the error is
+    /// returned. Buffered documents are also written after
+    /// `WriteBehind::delay`, once `WriteBehind::max_pending` is reached
+    /// and when the last handle of the store is dropped (see
+    /// `Config::write_behind` for what happens if that fails).
+    pub fn flush(&self) -> Result<()> {
+        match &self.0 {
+            StoreType::File(f, _) => f.read().flush(),
+            StoreType::Memory(..) => Ok(()),
+        }
+    }
+
+    /// Returns the hit and miss counters of the document cache or `None`
+    /// if the store has no cache (see `Config::cache`).
+    #[must_use]
+    pub fn cache_stats(&self) -> Option<CacheStats> {
+        match &self.0 {
+            StoreType::File(f, _) => f.read().cache_stats(),
+            StoreType::Memory(..) => None,
+        }
+    }
+
+    /// Writes a snapshot of all documents to `path`.
+    ///
+    /// The snapshot uses the directory or single-file layout of this store's
+    /// configuration and replaces all documents that are already stored
+    /// there, so it can be opened with `new_with_cfg` or `load_into_memory`.
+    /// In the directory layout every other `*.json` file in the target
+    /// directory is deleted, and it fails without writing anything if an
+    /// ID isn't a plain file name (e.g. `a/b` or `v1.0`).
+    /// Only the layout and formatting settings are taken over, so the
+    /// snapshot is written right away even if this store is read-only or
+    /// uses write-behind mode.
+    /// Metadata, history and trash are not included.
+    pub fn persist_to<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let docs = self.all_values()?;
+        let cfg = self.cfg();
+        let cfg = Config {
+            single: cfg.single,
+            pretty: cfg.pretty,
+            indent: cfg.indent,
+            ..Config::default()
+        };
+        if !cfg.single {
+            check_file_names(&docs)?;
+        }
+        FileStore::new_with_clock(path, cfg, Arc::new(SystemClock))?.write_snapshot(&docs)
+    }
+
+    /// Starts writing snapshots (see `persist_to`) to `path` every `interval`.
+    ///
+    /// A final snapshot is written when the returned `Autosave` is dropped,
+    /// so pass `None` as `interval` to only save on drop.
+    #[must_use]
+    pub fn autosave<P: AsRef<Path>>(&self, path: P, interval: Option<Duration>) -> Autosave {
+        Autosave::new(self.clone(), path.as_ref().to_path_buf(), interval)
+    }
+
+    /// Copies all documents into a store at `to_path` with the given
+    /// configuration, e.g. to migrate from the single-file to the directory
+    /// layout, and returns that store.
+    ///
+    /// IDs are kept and documents that already exist at `to_path` are
+    /// replaced. It fails without writing anything if a document can't be
+    /// read or, when converting to the directory layout, if an ID isn't a
+    /// plain file name (e.g. `a/b` or `v1.0`). Documents are written atomically and those that are already
+    /// up to date are skipped, so an interrupted conversion can be resumed
+    /// by calling `convert` again.
+    /// Metadata, history and trash are not converted.
+    pub fn convert<P: AsRef<Path>>(&self, to_path: P, cfg: Config) -> Result<Self> {
+        let docs = self.all_strict::<Value>()?;
+        if !cfg.single && memory_name(to_path.as_ref()).is_none() {
+            check_file_names(&docs)?;
+        }
+        let target = Self::new_with_cfg(to_path, cfg)?;
+        let existing = target.all_values()?;
+        let changed = docs
+            .iter()
+            .filter(|(id, doc)| existing.get(*id) != Some(*doc))
+            .map(|(id, doc)| (id.as_str(), doc.clone()))
+            .collect::<Vec<_>>();
+        for res in target.save_many(&changed)? {
+            res?;
+        }
+        target.flush()?;
+        Ok(target)
+    }
+
     pub fn save<T>(&self, obj: &T) -> Result<String>
     where
         for<'de> T: Serialize + Deserialize<'de>,
     {
         match &self.0 {
             StoreType::File(f, _) => f.write().save(obj),
-            StoreType::Memory(m) => m.save(obj),
+            StoreType::Memory(m, _) => m.save(obj),
         }
     }
 
@@ -135,7 +340,47 @@ impl Store {
     {
         match &self.0 {
             StoreType::File(f, _) => f.write().save_with_id(obj, id),
-            StoreType::Memory(m) => m.save_with_id(obj, id),
+            StoreType::Memory(m, _) => m.save_with_id(obj, id),
+        }
+    }
+
+    /// Saves several documents at once and returns the result for each of them.
+    ///
+    /// In single-file mode the JSON file is read and written only once.
+    /// The returned `Err` is reserved for failures that affect the whole batch,
+    /// in which case none of the documents has been saved.
+    pub fn save_many<T>(&self, docs: &[(&str, T)]) -> Result<Vec<Result<String>>>
+    where
+        for<'de> T: Serialize + Deserialize<'de>,
+    {
+        match &self.0 {
+            StoreType::File(f, _) => f.write().save_many(docs),
+            StoreType::Memory(m, _) => m.save_many(docs),
+        }
+    }
+
+    /// Saves an untyped JSON document.
+    ///
+    /// This is the same as `save_with_id`, but doesn't need a type annotation.
+    pub fn save_value(&self, doc: &Value, id: &str) -> Result<String> {
+        match &self.0 {
+            StoreType::File(f, _) => f.write().save_value(doc, id),
+            StoreType::Memory(m, _) => m.save_value(doc, id),
+        }
+    }
+
+    /// Saves a document that expires after the given time-to-live.
+    ///
+    /// Expired documents are invisible to `get` and `all`, but stay on disk
+    /// until they're removed by `purge_expired` (see also `spawn_sweeper`).
+    /// Saving the document again via `save_with_id` removes the expiration.
+    pub fn save_with_ttl<T>(&self, obj: &T, id: &str, ttl: Duration) -> Result<String>
+    where
+        for<'de> T: Serialize + Deserialize<'de>,
+    {
+        match &self.0 {
+            StoreType::File(f, _) => f.write().save_with_ttl(obj, id, ttl),
+            StoreType::Memory(m, _) => m.save_with_ttl(obj, id, ttl),
         }
     }
 
@@ -145,7 +390,277 @@ impl Store {
     {
         match &self.0 {
             StoreType::File(f, _) => f.read().get(id),
-            StoreType::Memory(m) => m.get(id),
+            StoreType::Memory(m, _) => m.get(id),
+        }
+    }
+
+    /// Loads a document as untyped JSON.
+    pub fn get_value(&self, id: &str) -> Result<Value> {
+        match &self.0 {
+            StoreType::File(f, _) => f.read().get_value(id),
+            StoreType::Memory(m, _) => m.get_value(id),
+        }
+    }
+
+    /// Loads several documents at once and returns the result for each of them.
+    pub fn get_many<T>(&self, ids: &[&str]) -> Result<Vec<Result<T>>>
+    where
+        for<'de> T: Deserialize<'de>,
+    {
+        match &self.0 {
+            StoreType::File(f, _) => f.read().get_many(ids),
+            StoreType::Memory(m, _) => m.get_many(ids),
+        }
+    }
+
+    /// Returns `true` if a document with the given ID exists.
+    ///
+    /// Unlike `get`, this doesn't decode the document.
+    pub fn exists(&self, id: &str) -> Result<bool> {
+        match &self.0 {
+            StoreType::File(f, _) => f.read().exists(id),
+            StoreType::Memory(m, _) => m.exists(id),
+        }
+    }
+
+    /// Returns the sorted IDs of all documents without reading them.
+    ///
+    /// Expired documents are not included.
+    pub fn keys(&self) -> Result<Vec<String>> {
+        match &self.0 {
+            StoreType::File(f, _) => f.read().keys(),
+            StoreType::Memory(m, _) => m.keys(),
+        }
+    }
+
+    /// Returns the number of documents without reading them.
+    pub fn count(&self) -> Result<usize> {
+        match &self.0 {
+            StoreType::File(f, _) => f.read().count(),
+            StoreType::Memory(m, _) => m.count(),
+        }
+    }
+
+    /// Returns `true` if the store contains no documents.
+    pub fn is_empty(&self) -> Result<bool> {
+        match &self.0 {
+            StoreType::File(f, _) => f.read().is_empty(),
+            StoreType::Memory(m, _) => m.is_empty(),
+        }
+    }
+
+    /// Returns the documents whose IDs start with `prefix`, ordered by ID.
+    ///
+    /// Only the documents that match (and fit into `opts.limit`) are read.
+    /// Like `all`, documents that can't be decoded as `T` are skipped.
+    pub fn scan_prefix<T>(&self, prefix: &str, opts: ScanOptions) -> Result<Vec<(String, T)>>
+    where
+        for<'de> T: Deserialize<'de>,
+    {
+        self.scan(&|id| id.starts_with(prefix), opts)
+    }
+
+    /// Returns the documents whose IDs are within `range`, ordered by ID.
+    ///
+    /// See `scan_prefix` for details.
+    ///
+    /// ```rust,no_run
+    /// # let db = jfs::Store::new("data").unwrap();
+    /// let docs = db.range::<serde_json::Value>("a".."m", jfs::ScanOptions::default());
+    /// ```
+    pub fn range<'a, T>(
+        &self,
+        range: impl RangeBounds<&'a str>,
+        opts: ScanOptions,
+    ) -> Result<Vec<(String, T)>>
+    where
+        for<'de> T: Deserialize<'de>,
+    {
+        self.scan(&|id| range.contains(&id), opts)
+    }
+
+    /// Returns up to `limit` documents whose IDs come after the cursor `after`.
+    ///
+    /// Pass `None` to get the first page and the returned `next` cursor
+    /// to get the following one. Documents are ordered by ID, so pages stay
+    /// stable while documents are added or removed.
+    /// Only the documents of the page (plus one to look ahead) are read.
+    /// A `limit` of zero is rejected with `ErrorKind::InvalidInput`.
+    pub fn page<T>(&self, after: Option<&str>, limit: usize) -> Result<Page<T>>
+    where
+        for<'de> T: Deserialize<'de>,
+    {
+        if limit == 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "the page limit must not be zero",
+            ));
+        }
+        let opts = ScanOptions {
+            limit: Some(limit.saturating_add(1)),
+            reverse: false,
+        };
+        let mut items = self.scan(&|id| after.is_none_or(|after| id > after), opts)?;
+        let next = if items.len() > limit {
+            items.truncate(limit);
+            items.last().map(|(id, _)| id.clone())
+        } else {
+            None
+        };
+        Ok(Page { items, next })
+    }
+
+    fn scan<T>(&self, filter: &dyn Fn(&str) -> bool, opts: ScanOptions) -> Result<Vec<(String, T)>>
+    where
+        for<'de> T: Deserialize<'de>,
+    {
+        match &self.0 {
+            StoreType::File(f, _) => f.read().scan(filter, opts),
+            StoreType::Memory(m, _) => m.scan(filter, opts),
+        }
+    }
+
+    /// Returns the part of a document that the JSON Pointer (RFC 6901)
+    /// refers to, e.g. `"/settings/theme"`.
+    pub fn get_path<T>(&self, id: &str, pointer: &str) -> Result<T>
+    where
+        for<'de> T: Deserialize<'de>,
+    {
+        match &self.0 {
+            StoreType::File(f, _) => f.read().get_path(id, pointer),
+            StoreType::Memory(m, _) => m.get_path(id, pointer),
+        }
+    }
+
+    /// Replaces the part of a document that the JSON Pointer refers to.
+    ///
+    /// The last segment of the pointer may also name a new member of an
+    /// object or `-` to append to an array (see `get_path`).
+    /// The document is updated atomically, like with `save_with_id`,
+    /// but keeps its expiration.
+    pub fn set_path<V>(&self, id: &str, pointer: &str, value: &V) -> Result<()>
+    where
+        V: Serialize,
+    {
+        match &self.0 {
+            StoreType::File(f, _) => f.write().set_path(id, pointer, value),
+            StoreType::Memory(m, _) => m.set_path(id, pointer, value),
+        }
+    }
+
+    /// Applies a JSON Merge Patch (RFC 7386) to a document.
+    ///
+    /// Members of `merge_patch` replace those of the document,
+    /// `null` members remove them. Like `set_path`, the document
+    /// is updated atomically and keeps its expiration.
+    pub fn patch<P>(&self, id: &str, merge_patch: &P) -> Result<()>
+    where
+        P: Serialize,
+    {
+        match &self.0 {
+            StoreType::File(f, _) => f.write().patch(id, merge_patch),
+            StoreType::Memory(m, _) => m.patch(id, merge_patch),
+        }
+    }
+
+    /// Applies a JSON Patch (RFC 6902) to a document.
+    ///
+    /// The document is only saved if all operations succeed,
+    /// otherwise an `ErrorKind::InvalidInput` error is returned.
+    /// See `patch` for details.
+    pub fn apply_patch(&self, id: &str, patch: &JsonPatch) -> Result<()> {
+        match &self.0 {
+            StoreType::File(f, _) => f.write().apply_patch(id, patch),
+            StoreType::Memory(m, _) => m.apply_patch(id, patch),
+        }
+    }
+
+    /// Validates all documents that are written from now on
+    /// against the given JSON Schema.
+    ///
+    /// Writes of documents that don't conform fail with an
+    /// `ErrorKind::InvalidData` error that wraps a `ValidationError`.
+    /// Pass `None` to remove the schema. The schema is not persisted,
+    /// it applies to this store and all its clones.
+    ///
+    /// This method is only available if the `jsonschema` feature is enabled.
+    #[cfg(feature = "jsonschema")]
+    pub fn set_schema(&self, schema: Option<&Value>) -> Result<()> {
+        match &self.0 {
+            StoreType::File(f, _) => f.read().schema().set(schema),
+            StoreType::Memory(m, _) => m.schema().set(schema),
+        }
+    }
+
+    /// Validates all existing documents against the schema
+    /// (see `set_schema`) and returns the IDs and errors of those
+    /// that don't conform.
+    ///
+    /// This method is only available if the `jsonschema` feature is enabled.
+    #[cfg(feature = "jsonschema")]
+    pub fn validate_all(&self) -> Result<BTreeMap<String, ValidationError>> {
+        match &self.0 {
+            StoreType::File(f, _) => f.read().validate_all(),
+            StoreType::Memory(m, _) => m.validate_all(),
+        }
+    }
+
+    /// Calls `callback` with the ID and content of every document that an
+    /// in-memory store evicts because `Config::max_entries` or
+    /// `Config::max_bytes` is exceeded.
+    ///
+    /// The callback replaces any previous one and is shared by all clones
+    /// of this store. File stores never evict documents.
+    pub fn on_evict<F>(&self, callback: F)
+    where
+        F: Fn(&str, Value) + Send + Sync + 'static,
+    {
+        if let StoreType::Memory(m, _) = &self.0 {
+            m.on_evict().set(Arc::new(callback));
+        }
+    }
+
+    /// Returns the metadata of a document.
+    ///
+    /// Creation time, modification time and revision are only available
+    /// if `Config::metadata` is enabled.
+    pub fn meta(&self, id: &str) -> Result<Metadata> {
+        match &self.0 {
+            StoreType::File(f, _) => f.read().meta(id),
+            StoreType::Memory(m, _) => m.meta(id),
+        }
+    }
+
+    /// Lists the prior versions of a document, oldest first.
+    ///
+    /// Versions are only recorded if `Config::history` is set.
+    /// The history of a deleted document is kept, so it can be restored
+    /// with `revert`.
+    pub fn history(&self, id: &str) -> Result<Vec<Version>> {
+        match &self.0 {
+            StoreType::File(f, _) => f.read().history(id),
+            StoreType::Memory(m, _) => m.history(id),
+        }
+    }
+
+    /// Returns a prior version of a document (see `history`).
+    pub fn get_at<T>(&self, id: &str, version: u64) -> Result<T>
+    where
+        for<'de> T: Deserialize<'de>,
+    {
+        match &self.0 {
+            StoreType::File(f, _) => f.read().get_at(id, version),
+            StoreType::Memory(m, _) => m.get_at(id, version),
+        }
+    }
+
+    /// Restores a prior version of a document (see `history`).
+    ///
+    /// The current content is archived as a new version.
+    pub fn revert(&self, id: &str, version: u64) -> Result<()> {
+        match &self.0 {
+            StoreType::File(f, _) => f.write().revert(id, version),
+            StoreType::Memory(m, _) => m.revert(id, version),
         }
     }
 
@@ -155,18 +670,172 @@ impl Store {
     {
         match &self.0 {
             StoreType::File(f, _) => f.read().all(),
-            StoreType::Memory(m) => m.all(),
+            StoreType::Memory(m, _) => m.all(),
+        }
+    }
+
+    /// Loads all documents as untyped JSON.
+    ///
+    /// Like `all`, unreadable documents are skipped.
+    pub fn all_values(&self) -> Result<BTreeMap<String, Value>> {
+        match &self.0 {
+            StoreType::File(f, _) => f.read().all_values(),
+            StoreType::Memory(m, _) => m.all_values(),
+        }
+    }
+
+    /// Like `all`, but fails if any document can't be read or decoded as `T`.
+    pub fn all_strict<T>(&self) -> Result<BTreeMap<String, T>>
+    where
+        for<'de> T: Deserialize<'de>,
+    {
+        match &self.0 {
+            StoreType::File(f, _) => f.read().all_strict(),
+            StoreType::Memory(m, _) => m.all_strict(),
+        }
+    }
+
+    /// Returns all documents that could be decoded as `T`
+    /// together with the IDs and errors of those that couldn't.
+    ///
+    /// `all` silently skips (but logs) such documents.
+    pub fn all_with_errors<T>(&self) -> Result<Decoded<T>>
+    where
+        for<'de> T: Deserialize<'de>,
+    {
+        match &self.0 {
+            StoreType::File(f, _) => f.read().all_with_errors(),
+            StoreType::Memory(m, _) => m.all_with_errors(),
         }
     }
 
     pub fn delete(&self, id: &str) -> Result<()> {
         match &self.0 {
             StoreType::File(f, _) => f.write().delete(id),
-            StoreType::Memory(m) => m.delete(id),
+            StoreType::Memory(m, _) => m.delete(id),
+        }
+    }
+
+    /// Deletes several documents at once and returns the result for each of them.
+    ///
+    /// See `save_many` for details.
+    pub fn delete_many(&self, ids: &[&str]) -> Result<Vec<Result<()>>> {
+        match &self.0 {
+            StoreType::File(f, _) => f.write().delete_many(ids),
+            StoreType::Memory(m, _) => m.delete_many(ids),
+        }
+    }
+
+    /// Deletes all documents and returns their number.
+    ///
+    /// Like `delete`, this honours `Config::history` and `Config::trash`,
+    /// so a cleared store can be recovered if those are enabled.
+    /// Files in the store directory that aren't documents are left untouched.
+    pub fn clear(&self) -> Result<usize> {
+        match &self.0 {
+            StoreType::File(f, _) => f.write().clear(),
+            StoreType::Memory(m, _) => m.clear(),
+        }
+    }
+
+    /// Restores a soft-deleted document from the trash.
+    ///
+    /// Documents are only moved into the trash on `delete`
+    /// if `Config::trash` is enabled.
+    /// Fails with `ErrorKind::AlreadyExists` if a document with the same ID
+    /// has been saved in the meantime.
+    pub fn restore(&self, id: &str) -> Result<()> {
+        match &self.0 {
+            StoreType::File(f, _) => f.write().restore(id),
+            StoreType::Memory(m, _) => m.restore(id),
+        }
+    }
+
+    /// Lists all soft-deleted documents.
+    pub fn list_trash(&self) -> Result<Vec<TrashEntry>> {
+        match &self.0 {
+            StoreType::File(f, _) => f.read().list_trash(),
+            StoreType::Memory(m, _) => m.list_trash(),
+        }
+    }
+
+    /// Permanently removes all documents from the trash that were deleted
+    /// at least `older_than` ago and returns their number.
+    ///
+    /// Use `Duration::ZERO` to empty the whole trash.
+    pub fn empty_trash(&self, older_than: Duration) -> Result<usize> {
+        match &self.0 {
+            StoreType::File(f, _) => f.write().empty_trash(older_than),
+            StoreType::Memory(m, _) => m.empty_trash(older_than),
+        }
+    }
+
+    /// Removes all expired documents and returns their number.
+    pub fn purge_expired(&self) -> Result<usize> {
+        match &self.0 {
+            StoreType::File(f, _) => f.write().purge_expired(),
+            StoreType::Memory(m, _) => m.purge_expired(),
+        }
+    }
+
+    /// Starts a background thread that calls `purge_expired`
+    /// every `interval`.
+    ///
+    /// The thread runs until the returned `Sweeper` is dropped.
+    #[must_use]
+    pub fn spawn_sweeper(&self, interval: Duration) -> Sweeper {
+        let store = self.clone();
+        Sweeper::spawn(interval, move || {
+            if let Err(err) = store.purge_expired() {
+                log::warn!("Could not purge expired documents: {err}");
+            }
+        })
+    }
+
+    /// Checks the integrity of the store.
+    ///
+    /// The returned report lists unreadable files, malformed JSON,
+    /// leftover temporary files, file names that don't map to a valid ID
+    /// and documents that can't be decoded as `T`.
+    /// Use `verify::<serde_json::Value>()` to skip the type check.
+    pub fn verify<T>(&self) -> Result<Report>
+    where
+        for<'de> T: Deserialize<'de>,
+    {
+        match &self.0 {
+            StoreType::File(f, _) => f.read().verify::<T>(),
+            StoreType::Memory(m, _) => m.verify::<T>(),
+        }
+    }
+
+    /// Checks the integrity of the store (see `verify`) and
+    /// quarantines or removes all bad entries according to `policy`.
+    ///
+    /// Unreadable files are left untouched.
+    /// The returned report lists all issues that were found.
+    pub fn repair<T>(&self, policy: RepairPolicy) -> Result<Report>
+    where
+        for<'de> T: Deserialize<'de>,
+    {
+        match &self.0 {
+            StoreType::File(f, _) => f.write().repair::<T>(policy),
+            StoreType::Memory(m, _) => m.repair::<T>(policy),
         }
     }
 }
 
+/// Checks that the documents can be stored in the directory layout
+/// without changing their IDs.
+fn check_file_names(docs: &BTreeMap<String, Value>) -> Result<()> {
+    docs.keys().try_for_each(|id| check_id_is_file_name(id))
+}
+
+/// Returns the name of an in-memory store (empty for unnamed stores)
+/// or `None` if `path` refers to the file system.
+fn memory_name(path: &Path) -> Option<&str> {
+    path.to_str().and_then(|p| p.strip_prefix(IN_MEMORY))
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -222,6 +891,382 @@ mod tests {
         multi_threaded_write(store);
     }
 
+    #[test]
+    fn sweeper() {
+        let store = Store::new(IN_MEMORY).unwrap();
+        store
+            .save_with_ttl(&Data { x: 1 }, "x", Duration::ZERO)
+            .unwrap();
+        let sweeper = store.spawn_sweeper(Duration::from_millis(10));
+        thread::sleep(Duration::from_millis(100));
+        drop(sweeper);
+        assert_eq!(store.purge_expired().unwrap(), 0);
+    }
+
+    fn paginate(store: Store) {
+        for x in 0..5 {
+            store.save_with_id(&Data { x }, &x.to_string()).unwrap();
+        }
+        let page = store.page::<Data>(None, 2).unwrap();
+        assert_eq!(page.items.len(), 2);
+        assert_eq!(page.next.as_deref(), Some("1"));
+        store.delete("1").unwrap();
+        let page = store.page::<Data>(page.next.as_deref(), 2).unwrap();
+        assert_eq!(page.items[0].0, "2");
+        assert_eq!(page.items[1].1.x, 3);
+        let page = store.page::<Data>(page.next.as_deref(), 2).unwrap();
+        assert_eq!(page.items.len(), 1);
+        assert_eq!(page.next, None);
+        let res = store.page::<Data>(None, 0);
+        assert_eq!(res.err().unwrap().kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn page_with_dir() {
+        let dir = tempdir().expect("Could not create temporary directory");
+        paginate(Store::new(dir.path()).unwrap());
+    }
+
+    #[test]
+    fn page_in_memory() {
+        paginate(Store::new(IN_MEMORY).unwrap());
+    }
+
+    fn patch(store: &Store) {
+        let doc = serde_json::json!({ "a": 1, "b": { "c": 2 } });
+        store.save_with_id(&doc, "x").unwrap();
+        let merge_patch = serde_json::json!({ "a": null, "b": { "d": 3 } });
+        store.patch("x", &merge_patch).unwrap();
+        let doc = store.get::<serde_json::Value>("x").unwrap();
+        assert_eq!(doc, serde_json::json!({ "b": { "c": 2, "d": 3 } }));
+
+        let patch: JsonPatch = serde_json::from_value(serde_json::json!([
+            { "op": "test", "path": "/b/c", "value": 2 },
+            { "op": "move", "from": "/b/c", "path": "/c" }
+        ]))
+        .unwrap();
+        store.apply_patch("x", &patch).unwrap();
+        let err = store.apply_patch("x", &patch).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        let doc = store.get::<serde_json::Value>("x").unwrap();
+        assert_eq!(doc, serde_json::json!({ "b": { "d": 3 }, "c": 2 }));
+    }
+
+    #[test]
+    fn patch_with_single_file() {
+        let dir = tempdir().expect("Could not create temporary directory");
+        let mut cfg = Config::default();
+        cfg.single = true;
+        patch(&Store::new_with_cfg(dir.path().join("db"), cfg).unwrap());
+    }
+
+    #[test]
+    fn patch_in_memory() {
+        patch(&Store::new(IN_MEMORY).unwrap());
+    }
+
+    #[cfg(feature = "jsonschema")]
+    fn validate(store: &Store) {
+        store.save_with_id(&Data { x: -1 }, "neg").unwrap();
+        let schema = serde_json::json!({
+            "type": "object",
+            "properties": { "x": { "type": "integer", "minimum": 0 } },
+            "required": ["x"]
+        });
+        store.set_schema(Some(&schema)).unwrap();
+
+        let err = store.save_with_id(&Data { x: -2 }, "a").unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        let err = err
+            .into_inner()
+            .unwrap()
+            .downcast::<ValidationError>()
+            .unwrap();
+        assert_eq!(err.violations.len(), 1);
+        assert_eq!(err.violations[0].path, "/x");
+        assert!(!store.exists("a").unwrap());
+
+        let res = store
+            .save_many(&[("b", Data { x: 1 }), ("c", Data { x: -1 })])
+            .unwrap();
+        assert!(res[0].is_ok());
+        assert!(res[1].is_err());
+        assert!(store.set_path("b", "/x", &-3).is_err());
+        assert!(store.patch("b", &serde_json::json!({ "x": null })).is_err());
+        assert_eq!(store.get::<Data>("b").unwrap().x, 1);
+
+        let invalid = store.validate_all().unwrap();
+        assert_eq!(invalid.keys().collect::<Vec<_>>(), ["neg"]);
+
+        store.set_schema(None).unwrap();
+        store.save_with_id(&Data { x: -2 }, "a").unwrap();
+        assert!(store
+            .set_schema(Some(&serde_json::json!({ "type": 5 })))
+            .is_err());
+    }
+
+    #[cfg(feature = "jsonschema")]
+    #[test]
+    fn validate_with_single_file() {
+        let dir = tempdir().expect("Could not create temporary directory");
+        let mut cfg = Config::default();
+        cfg.single = true;
+        validate(&Store::new_with_cfg(dir.path().join("db"), cfg).unwrap());
+    }
+
+    #[cfg(feature = "jsonschema")]
+    #[test]
+    fn validate_in_memory() {
+        validate(&Store::new(IN_MEMORY).unwrap());
+    }
+
+    fn values(store: &Store) {
+        let doc = serde_json::json!({ "name": "foo", "tags": ["a", "b"] });
+        store.save_value(&doc, "foo").unwrap();
+        store.save_value(&Value::Null, "bar").unwrap();
+        assert_eq!(store.get_value("foo").unwrap(), doc);
+        let all = store.all_values().unwrap();
+        assert_eq!(all.len(), 2);
+        assert_eq!(all["bar"], Value::Null);
+        assert!(store.get_value("baz").is_err());
+    }
+
+    #[test]
+    fn values_with_dir() {
+        let dir = tempdir().expect("Could not create temporary directory");
+        values(&Store::new(dir.path()).unwrap());
+    }
+
+    #[test]
+    fn values_in_memory() {
+        values(&Store::new(IN_MEMORY).unwrap());
+    }
+
+    #[test]
+    fn cache() {
+        let dir = tempdir().expect("Could not create temporary directory");
+        let mut cfg = Config::default();
+        cfg.cache = Some(10);
+        let store = Store::new_with_cfg(dir.path(), cfg).unwrap();
+        store.save_with_id(&Data { x: 1 }, "a").unwrap();
+        for _ in 0..3 {
+            assert_eq!(store.get::<Data>("a").unwrap().x, 1);
+        }
+        assert!(store.get::<Data>("b").is_err());
+        let stats = store.cache_stats().unwrap();
+        assert_eq!((stats.hits, stats.misses), (3, 1));
+        assert!(Store::new(dir.path()).unwrap().cache_stats().is_none());
+        assert!(Store::new(IN_MEMORY).unwrap().cache_stats().is_none());
+    }
+
+    #[test]
+    fn write_behind() {
+        let dir = tempdir().expect("Could not create temporary directory");
+        let path = dir.path().join("db");
+        let mut cfg = Config::default();
+        cfg.single = true;
+        cfg.write_behind = Some(WriteBehind {
+            delay: Duration::from_secs(3600),
+            ..WriteBehind::default()
+        });
+        let store = Store::new_with_cfg(&path, cfg).unwrap();
+        let other = store.clone();
+        store.save_with_id(&Data { x: 1 }, "a").unwrap();
+        store.flush().unwrap();
+        other.save_with_id(&Data { x: 2 }, "b").unwrap();
+        let count = || {
+            Store::new_with_cfg(
+                &path,
+                Config {
+                    single: true,
+                    ..Config::default()
+                },
+            )
+            .unwrap()
+            .count()
+            .unwrap()
+        };
+        assert_eq!(count(), 1);
+        drop(store);
+        assert_eq!(count(), 1);
+        drop(other);
+        assert_eq!(count(), 2);
+    }
+
+    #[test]
+    fn convert() {
+        let dir = tempdir().expect("Could not create temporary directory");
+        let mut single = Config::default();
+        single.single = true;
+        let store = Store::new_with_cfg(dir.path().join("db"), single).unwrap();
+        for x in 0..3 {
+            store.save_with_id(&Data { x }, &format!("id-{x}")).unwrap();
+        }
+
+        // An interrupted conversion.
+        let partial = Store::new(dir.path().join("dir")).unwrap();
+        partial.save_with_id(&Data { x: 0 }, "id-0").unwrap();
+        partial.save_with_id(&Data { x: 9 }, "id-1").unwrap();
+
+        let target = store
+            .convert(dir.path().join("dir"), Config::default())
+            .unwrap();
+        assert_eq!(target.path(), dir.path().join("dir"));
+        assert!(dir.path().join("dir/id-2.json").exists());
+        let docs = target.all::<Data>().unwrap();
+        assert_eq!(docs.values().map(|d| d.x).collect::<Vec<_>>(), [0, 1, 2]);
+
+        let back = target.convert(dir.path().join("back"), single).unwrap();
+        assert_eq!(back.path(), dir.path().join("back.json"));
+        assert_eq!(back.all_values().unwrap(), store.all_values().unwrap());
+
+        for id in ["v1.0", "a/b", "..", ""] {
+            let store = Store::new_with_cfg(IN_MEMORY, single).unwrap();
+            store.save_with_id(&Data { x: 0 }, "ok").unwrap();
+            store.save_with_id(&Data { x: 1 }, id).unwrap();
+            let res = store.convert(dir.path().join("bad"), Config::default());
+            assert_eq!(res.err().unwrap().kind(), ErrorKind::InvalidInput);
+            assert!(!dir.path().join("bad").exists());
+        }
+    }
+
+    fn persist_and_load(cfg: Config) {
+        let dir = tempdir().expect("Could not create temporary directory");
+        let path = dir.path().join("db");
+        assert!(Store::load_into_memory(&path, cfg).is_err());
+        let store = Store::new_with_cfg(IN_MEMORY, cfg).unwrap();
+        store.save_with_id(&Data { x: 1 }, "a").unwrap();
+        store.save_with_id(&Data { x: 2 }, "b").unwrap();
+        store.persist_to(&path).unwrap();
+        store.delete("a").unwrap();
+        store.persist_to(&path).unwrap();
+
+        let on_disk = Store::new_with_cfg(&path, cfg).unwrap();
+        assert_eq!(on_disk.keys().unwrap(), ["b"]);
+        let loaded = Store::load_into_memory(&path, cfg).unwrap();
+        assert_eq!(loaded.path(), Path::new(IN_MEMORY));
+        assert_eq!(loaded.get::<Data>("b").unwrap().x, 2);
+        assert_eq!(loaded.count().unwrap(), 1);
+    }
+
+    #[test]
+    fn persist_and_load_with_dir() {
+        persist_and_load(Config::default());
+    }
+
+    #[test]
+    fn persist_and_load_with_single_file() {
+        let mut cfg = Config::default();
+        cfg.single = true;
+        persist_and_load(cfg);
+    }
+
+    #[test]
+    fn persist_dotted_ids() {
+        let dir = tempdir().expect("Could not create temporary directory");
+        let path = dir.path().join("db");
+        let store = Store::new(IN_MEMORY).unwrap();
+        store.save_with_id(&Data { x: 1 }, "v1").unwrap();
+        store.persist_to(&path).unwrap();
+        store.delete("v1").unwrap();
+        store.save_with_id(&Data { x: 2 }, "v1.0").unwrap();
+        store.save_with_id(&Data { x: 3 }, "v1.5").unwrap();
+        let res = store.persist_to(&path);
+        assert_eq!(res.err().unwrap().kind(), ErrorKind::InvalidInput);
+        let loaded = Store::load_into_memory(&path, Config::default()).unwrap();
+        assert_eq!(loaded.get::<Data>("v1").unwrap().x, 1);
+
+        let on_disk =
+            FileStore::new_with_clock(&path, Config::default(), Arc::new(SystemClock)).unwrap();
+        let res = on_disk.write_snapshot(&store.all_values().unwrap());
+        assert_eq!(res.err().unwrap().kind(), ErrorKind::InvalidInput);
+        assert!(path.join("v1.json").exists());
+    }
+
+    #[test]
+    fn persist_read_only() {
+        let dir = tempdir().expect("Could not create temporary directory");
+        let mut cfg = Config::default();
+        cfg.single = true;
+        let store = Store::new_with_cfg(dir.path().join("db"), cfg).unwrap();
+        store.save_with_id(&Data { x: 1 }, "a").unwrap();
+
+        cfg.read_only = true;
+        cfg.write_behind = Some(WriteBehind::default());
+        let store = Store::new_with_cfg(dir.path().join("db"), cfg).unwrap();
+        store.persist_to(dir.path().join("copy")).unwrap();
+        let copy = std::fs::read_to_string(dir.path().join("copy.json")).unwrap();
+        assert_eq!(copy, r#"{"a":{"x":1}}"#);
+    }
+
+    #[test]
+    fn autosave() {
+        let dir = tempdir().expect("Could not create temporary directory");
+        let store = Store::new(IN_MEMORY).unwrap();
+        let autosave = store.autosave(dir.path(), Some(Duration::from_millis(10)));
+        store.save_with_id(&Data { x: 1 }, "a").unwrap();
+        thread::sleep(Duration::from_millis(100));
+        assert!(dir.path().join("a.json").exists());
+        store.save_with_id(&Data { x: 2 }, "b").unwrap();
+        drop(autosave);
+        assert!(dir.path().join("b.json").exists());
+
+        let autosave = store.autosave(dir.path(), None);
+        store.delete("a").unwrap();
+        autosave.save().unwrap();
+        assert!(!dir.path().join("a.json").exists());
+    }
+
+    #[test]
+    fn named_in_memory() {
+        let a = Store::new("::memory::named_in_memory").unwrap();
+        assert_eq!(a.path(), Path::new("::memory::named_in_memory"));
+        a.save_with_id(&Data { x: 1 }, "x").unwrap();
+        let b = Store::new("::memory::named_in_memory").unwrap();
+        assert_eq!(b.get::<Data>("x").unwrap().x, 1);
+        assert!(Store::new(IN_MEMORY).unwrap().is_empty().unwrap());
+        assert!(Store::new("::memory::other").unwrap().is_empty().unwrap());
+
+        assert!(Store::drop_named("::memory::named_in_memory"));
+        assert!(!Store::drop_named("::memory::named_in_memory"));
+        assert!(!Store::drop_named(IN_MEMORY));
+        assert_eq!(a.get::<Data>("x").unwrap().x, 1);
+        let c = Store::new("::memory::named_in_memory").unwrap();
+        assert!(c.is_empty().unwrap());
+    }
+
+    #[test]
+    fn evict_in_memory() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        let mut cfg = Config::default();
+        cfg.max_entries = Some(10);
+        let db = Store::new_with_cfg(IN_MEMORY, cfg).unwrap();
+        let evicted = Arc::new(AtomicUsize::new(0));
+        let e = Arc::clone(&evicted);
+        db.on_evict(move |_, _| {
+            e.fetch_add(1, Ordering::Relaxed);
+        });
+        db.save_with_id(&Data { x: 0 }, "hot").unwrap();
+        let threads = (1..=4)
+            .map(|t| {
+                let db = db.clone();
+                std::thread::spawn(move || {
+                    for i in 0..25 {
+                        db.get::<Data>("hot").unwrap();
+                        db.save_with_id(&Data { x: i }, &format!("{t}-{i}"))
+                            .unwrap();
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for t in threads {
+            t.join().unwrap();
+        }
+        assert_eq!(db.count().unwrap(), 10);
+        assert_eq!(evicted.load(Ordering::Relaxed), 91);
+        assert!(db.exists("hot").unwrap());
+    }
+
     #[test]
     fn multi_threaded_write_in_memory() {
         #[derive(Serialize, Deserialize)]
@@ -1,18 +1,73 @@
 use std::{
     collections::{BTreeMap, HashMap},
+    fmt,
     io::{Error, ErrorKind, Result},
-    sync::Arc,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
 };
 
-use uuid::Uuid;
 use parking_lot::{Mutex, RwLock};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
 
-use crate::json_store::JsonStore;
+use crate::{
+    evict::{Candidates, Listener, Usage},
+    history::{self, Archived, Retention, Version},
+    json_store::{Decoded, JsonStore},
+    meta::{now_millis, Clock, Meta, Metadata, SystemClock},
+    scan::{self, ScanOptions},
+    schema::Schema,
+    trash::{TrashEntry, Trashed},
+    verify::{Issue, RepairPolicy, Report},
+    Config,
+};
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone)]
 pub struct MemoryStore {
-    mem: Arc<RwLock<HashMap<String, Mutex<String>>>>,
+    mem: Arc<RwLock<HashMap<String, Entry>>>,
+    meta: Arc<RwLock<HashMap<String, Meta>>>,
+    history: Arc<RwLock<HashMap<String, Vec<Archived>>>>,
+    trash: Arc<RwLock<BTreeMap<String, Trashed>>>,
+    cfg: Config,
+    clock: Arc<dyn Clock>,
+    schema: Schema,
+    /// Counts accesses to order documents for eviction.
+    ticks: Arc<AtomicU64>,
+    /// The total size of all documents.
+    /// Only tracked if `Config::max_bytes` is set.
+    bytes: Arc<AtomicUsize>,
+    /// The documents that are evicted next.
+    candidates: Arc<Mutex<Candidates>>,
+    on_evict: Listener,
+}
+
+#[derive(Debug)]
+struct Entry {
+    doc: Mutex<Doc>,
+    /// The size of the document as JSON text.
+    /// Only tracked if `Config::max_bytes` is set.
+    size: AtomicUsize,
+    usage: Usage,
+}
+
+/// A document as it is kept in memory (see `Config::parsed`).
+#[derive(Debug)]
+enum Doc {
+    Text(String),
+    Parsed(Arc<Value>),
+}
+
+/// The process-wide registry of named in-memory stores.
+static NAMED: Mutex<BTreeMap<String, MemoryStore>> = Mutex::new(BTreeMap::new());
+
+impl Default for MemoryStore {
+    fn default() -> Self {
+        Self::new(Config::default(), Arc::new(SystemClock))
+    }
 }
 
 impl JsonStore for MemoryStore {
@@ -27,16 +82,15 @@ impl JsonStore for MemoryStore {
     where
         for<'de> T: Serialize + Deserialize<'de>,
     {
-        let json = serde_json::to_string(&obj).map_err(|err| Error::new(ErrorKind::Other, err))?;
-        let map = self.mem.read();
-        if let Some(val) = map.get(id) {
-            let mut value_guard = val.lock();
-            *value_guard = json;
-            return Ok(id.to_owned());
-        }
-        drop(map);
-        let mut map = self.mem.write();
-        map.insert(id.to_owned(), Mutex::new(json));
+        self.save_document(obj, id, None)?;
+        Ok(id.to_owned())
+    }
+
+    fn save_with_ttl<T>(&self, obj: &T, id: &str, ttl: Duration) -> Result<String>
+    where
+        for<'de> T: Serialize + Deserialize<'de>,
+    {
+        self.save_document(obj, id, Some(ttl))?;
         Ok(id.to_owned())
     }
 
@@ -44,46 +98,607 @@ impl JsonStore for MemoryStore {
     where
         for<'de> T: Deserialize<'de>,
     {
+        if self.is_expired(id, self.now()) {
+            return Err(Error::new(ErrorKind::NotFound, "no such object"));
+        }
         let map = self.mem.read();
         let value = map
             .get(id)
             .ok_or_else(|| Error::new(ErrorKind::NotFound, "no such object"))?;
-        let value_guard = value.lock();
-        serde_json::from_str(&value_guard).map_err(|err| Error::new(ErrorKind::Other, err))
+        self.touch(value);
+        decode(&value.doc)
+    }
+
+    fn exists(&self, id: &str) -> Result<bool> {
+        Ok(self.mem.read().contains_key(id) && !self.is_expired(id, self.now()))
+    }
+
+    fn keys(&self) -> Result<Vec<String>> {
+        let now = self.now();
+        let meta = self.meta.read();
+        let mut ids = self
+            .mem
+            .read()
+            .keys()
+            .filter(|id| !meta.get(*id).is_some_and(|m| m.is_expired(now)))
+            .cloned()
+            .collect::<Vec<_>>();
+        ids.sort();
+        Ok(ids)
+    }
+
+    fn count(&self) -> Result<usize> {
+        let now = self.now();
+        let meta = self.meta.read();
+        Ok(self
+            .mem
+            .read()
+            .keys()
+            .filter(|id| !meta.get(*id).is_some_and(|m| m.is_expired(now)))
+            .count())
+    }
+
+    fn scan<T>(&self, filter: &dyn Fn(&str) -> bool, opts: ScanOptions) -> Result<Vec<(String, T)>>
+    where
+        for<'de> T: Deserialize<'de>,
+    {
+        let limit = opts.limit.unwrap_or(usize::MAX);
+        let now = self.now();
+        let meta = self.meta.read();
+        let map = self.mem.read();
+        let mut result = vec![];
+        for id in scan::select(map.keys().map(String::as_str), filter, opts) {
+            if result.len() == limit {
+                break;
+            }
+            if meta.get(id).is_some_and(|m| m.is_expired(now)) {
+                continue;
+            }
+            match decode(&map[id].doc) {
+                Ok(x) => result.push((id.to_owned(), x)),
+                Err(err) => log::warn!("Skipping document '{id}': {err}"),
+            }
+        }
+        Ok(result)
+    }
+
+    #[cfg(feature = "jsonschema")]
+    fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    fn update<F>(&self, id: &str, f: F) -> Result<()>
+    where
+        F: FnOnce(&mut Value) -> Result<()>,
+    {
+        let now = self.now();
+        let mut meta = self.meta.write();
+        if meta.get(id).is_some_and(|m| m.is_expired(now)) {
+            return Err(Error::new(ErrorKind::NotFound, "no such object"));
+        }
+        let map = self.mem.read();
+        let entry = map
+            .get(id)
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, "no such object"))?;
+        let mut value_guard = entry.doc.lock();
+        let mut doc = value_guard.to_value()?;
+        let prev = self.cfg.history.is_some().then(|| doc.clone());
+        f(&mut doc)?;
+        self.schema.check(&doc)?;
+        let doc = Doc::from_value(doc, self.cfg.parsed)?;
+        self.set_size(entry, &doc);
+        *value_guard = doc;
+        drop(value_guard);
+        self.touch(entry);
+        drop(map);
+        if let (Some(retention), Some(prev)) = (&self.cfg.history, prev) {
+            let mut history = self.history.write();
+            history::archive(
+                history.entry(id.to_owned()).or_default(),
+                prev,
+                now,
+                retention,
+            );
+        }
+        let prev = meta.get(id);
+        match Meta::next(prev, now, self.cfg.metadata, prev.and_then(|m| m.ttl(now))) {
+            Some(m) => meta.insert(id.to_owned(), m),
+            None => meta.remove(id),
+        };
+        drop(meta);
+        self.evict(id);
+        Ok(())
+    }
+
+    fn meta(&self, id: &str) -> Result<Metadata> {
+        let meta = self.meta.read().get(id).cloned().unwrap_or_default();
+        if meta.is_expired(self.now()) {
+            return Err(Error::new(ErrorKind::NotFound, "no such object"));
+        }
+        let size = self
+            .mem
+            .read()
+            .get(id)
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, "no such object"))?
+            .doc
+            .lock()
+            .len();
+        Ok(meta.to_metadata(size as u64))
+    }
+
+    fn history(&self, id: &str) -> Result<Vec<Version>> {
+        Ok(self
+            .history
+            .read()
+            .get(id)
+            .map(|h| h.iter().map(Archived::to_version).collect())
+            .unwrap_or_default())
+    }
+
+    fn get_at<T>(&self, id: &str, version: u64) -> Result<T>
+    where
+        for<'de> T: Deserialize<'de>,
+    {
+        let history = self.history.read();
+        let archived = history
+            .get(id)
+            .and_then(|h| h.iter().find(|a| a.version == version))
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, "no such version"))?;
+        T::deserialize(&archived.doc).map_err(|err| Error::new(ErrorKind::Other, err))
     }
 
-    fn all<T>(&self) -> Result<BTreeMap<String, T>>
+    fn all_with_errors<T>(&self) -> Result<Decoded<T>>
     where
         for<'de> T: Deserialize<'de>,
     {
         let mut result = BTreeMap::new();
+        let mut errors = vec![];
+        let now = self.now();
+        let meta = self.meta.read();
         let map = self.mem.read();
         for x in map.iter() {
             let (k, v) = x;
-            let value_guard = v.lock();
-            if let Ok(r) = serde_json::from_str(&value_guard) {
-                result.insert(k.clone(), r);
+            if meta.get(k).is_some_and(|m| m.is_expired(now)) {
+                continue;
+            }
+            match decode(&v.doc) {
+                Ok(r) => {
+                    result.insert(k.clone(), r);
+                }
+                Err(err) => errors.push((k.clone(), err)),
             }
         }
-        Ok(result)
+        Ok((result, errors))
     }
 
     fn delete(&self, id: &str) -> Result<()> {
+        if let Some(retention) = &self.cfg.history {
+            self.archive(id, retention)?;
+        }
+        let mut meta = self.meta.write();
         let mut map = self.mem.write();
-        if map.contains_key(id) {
-            map.remove(id);
+        if let Some(value) = map.remove(id) {
+            self.untrack(&value);
+            meta.remove(id);
+            if self.cfg.trash {
+                let trashed = Trashed {
+                    deleted_at: self.now(),
+                    doc: value.doc.into_inner().to_value()?,
+                };
+                self.trash.write().insert(id.to_owned(), trashed);
+            }
         } else {
             return Err(Error::new(ErrorKind::NotFound, "no such object"));
         }
         Ok(())
     }
+
+    fn clear(&self) -> Result<usize> {
+        let ids = self.mem.read().keys().cloned().collect::<Vec<_>>();
+        let mut count = 0;
+        for id in ids {
+            match self.delete(&id) {
+                Ok(()) => count += 1,
+                // The document was deleted in the meantime.
+                Err(err) if err.kind() == ErrorKind::NotFound => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(count)
+    }
+
+    fn restore(&self, id: &str) -> Result<()> {
+        let trashed = self
+            .trash
+            .write()
+            .remove(id)
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, "no such object in trash"))?;
+        let res = if self.mem.read().contains_key(id) && !self.is_expired(id, self.now()) {
+            Err(Error::new(
+                ErrorKind::AlreadyExists,
+                "object already exists",
+            ))
+        } else {
+            self.save_document(&trashed.doc, id, None)
+        };
+        if res.is_err() {
+            self.trash.write().insert(id.to_owned(), trashed);
+        }
+        res
+    }
+
+    fn list_trash(&self) -> Result<Vec<TrashEntry>> {
+        Ok(self
+            .trash
+            .read()
+            .iter()
+            .map(|(id, t)| t.to_entry(id))
+            .collect())
+    }
+
+    fn empty_trash(&self, older_than: Duration) -> Result<usize> {
+        let now = self.now();
+        let mut trash = self.trash.write();
+        let len = trash.len();
+        trash.retain(|_, t| !t.is_older_than(older_than, now));
+        Ok(len - trash.len())
+    }
+
+    fn purge_expired(&self) -> Result<usize> {
+        let now = self.now();
+        let mut meta = self.meta.write();
+        let mut map = self.mem.write();
+        let expired = meta
+            .iter()
+            .filter(|(_, m)| m.is_expired(now))
+            .map(|(id, _)| id.clone())
+            .collect::<Vec<_>>();
+        for id in &expired {
+            meta.remove(id);
+            if let Some(entry) = map.remove(id) {
+                self.untrack(&entry);
+            }
+        }
+        Ok(expired.len())
+    }
+
+    fn verify<T>(&self) -> Result<Report>
+    where
+        for<'de> T: Deserialize<'de>,
+    {
+        let mut report = Report::default();
+        let map = self.mem.read();
+        for (id, v) in map.iter() {
+            if let Err(error) = decode::<T>(&v.doc) {
+                report.issues.push(Issue::Undecodable {
+                    id: id.clone(),
+                    error,
+                });
+            }
+        }
+        Ok(report)
+    }
+
+    /// There is no quarantine area for in-memory stores,
+    /// so bad entries are always removed.
+    fn repair<T>(&self, _: RepairPolicy) -> Result<Report>
+    where
+        for<'de> T: Deserialize<'de>,
+    {
+        let report = self.verify::<T>()?;
+        let mut meta = self.meta.write();
+        let mut map = self.mem.write();
+        for issue in &report.issues {
+            if let Issue::Undecodable { id, .. } = issue {
+                if let Some(entry) = map.remove(id) {
+                    self.untrack(&entry);
+                    meta.remove(id);
+                }
+            }
+        }
+        Ok(report)
+    }
+}
+
+impl MemoryStore {
+    pub fn new(cfg: Config, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            mem: Arc::default(),
+            meta: Arc::default(),
+            history: Arc::default(),
+            trash: Arc::default(),
+            cfg,
+            clock,
+            schema: Schema::default(),
+            ticks: Arc::default(),
+            bytes: Arc::default(),
+            candidates: Arc::default(),
+            on_evict: Listener::default(),
+        }
+    }
+
+    /// Returns the store registered under `name` or registers a new one.
+    ///
+    /// `cfg` and `clock` are only used if the store doesn't exist yet.
+    pub fn named(name: &str, cfg: Config, clock: Arc<dyn Clock>) -> Self {
+        NAMED
+            .lock()
+            .entry(name.to_owned())
+            .or_insert_with(|| Self::new(cfg, clock))
+            .clone()
+    }
+
+    /// Removes a store from the registry.
+    pub fn drop_named(name: &str) -> bool {
+        NAMED.lock().remove(name).is_some()
+    }
+
+    pub const fn cfg(&self) -> &Config {
+        &self.cfg
+    }
+
+    pub const fn on_evict(&self) -> &Listener {
+        &self.on_evict
+    }
+
+    fn now(&self) -> u64 {
+        now_millis(&*self.clock)
+    }
+
+    /// Stores a document and updates its metadata.
+    fn save_document<T: Serialize>(&self, obj: &T, id: &str, ttl: Option<Duration>) -> Result<()> {
+        self.schema.check(obj)?;
+        if let Some(retention) = &self.cfg.history {
+            self.archive(id, retention)?;
+        }
+        if !self.cfg.metadata && ttl.is_none() {
+            self.put(obj, id)?;
+            if self.meta.read().contains_key(id) {
+                self.meta.write().remove(id);
+            }
+        } else {
+            let mut meta = self.meta.write();
+            self.put(obj, id)?;
+            match Meta::next(meta.get(id), self.now(), self.cfg.metadata, ttl) {
+                Some(m) => meta.insert(id.to_owned(), m),
+                None => meta.remove(id),
+            };
+        }
+        self.evict(id);
+        Ok(())
+    }
+
+    /// Stores the current content of a document (if any) as a new prior version.
+    fn archive(&self, id: &str, retention: &Retention) -> Result<()> {
+        let doc: Value = match self.mem.read().get(id) {
+            Some(v) => v.doc.lock().to_value()?,
+            None => return Ok(()),
+        };
+        let mut history = self.history.write();
+        history::archive(
+            history.entry(id.to_owned()).or_default(),
+            doc,
+            self.now(),
+            retention,
+        );
+        Ok(())
+    }
+
+    fn put<T: Serialize>(&self, obj: &T, id: &str) -> Result<()> {
+        let doc = Doc::new(obj, self.cfg.parsed)?;
+        let map = self.mem.read();
+        if let Some(val) = map.get(id) {
+            let mut value_guard = val.doc.lock();
+            self.set_size(val, &doc);
+            *value_guard = doc;
+            drop(value_guard);
+            self.touch(val);
+            return Ok(());
+        }
+        drop(map);
+        let entry = Entry::new(doc);
+        self.set_size(&entry, &entry.doc.lock());
+        self.touch(&entry);
+        let rank = entry.usage.rank(self.cfg.eviction);
+        let mut map = self.mem.write();
+        if let Some(old) = map.insert(id.to_owned(), entry) {
+            self.untrack(&old);
+        }
+        if self.is_bounded() {
+            // New documents may rank below the current candidates.
+            self.candidates.lock().offer(rank, id.to_owned());
+        }
+        Ok(())
+    }
+
+    const fn is_bounded(&self) -> bool {
+        self.cfg.max_entries.is_some() || self.cfg.max_bytes.is_some()
+    }
+
+    /// Marks a document as used.
+    fn touch(&self, entry: &Entry) {
+        if self.is_bounded() {
+            entry
+                .usage
+                .touch(self.ticks.fetch_add(1, Ordering::Relaxed));
+        }
+    }
+
+    fn set_size(&self, entry: &Entry, doc: &Doc) {
+        if self.cfg.max_bytes.is_some() {
+            let size = doc.len();
+            self.bytes.fetch_add(size, Ordering::Relaxed);
+            let old = entry.size.swap(size, Ordering::Relaxed);
+            self.bytes.fetch_sub(old, Ordering::Relaxed);
+        }
+    }
+
+    /// Subtracts the size of a removed document from the total.
+    fn untrack(&self, entry: &Entry) {
+        self.bytes
+            .fetch_sub(entry.size.load(Ordering::Relaxed), Ordering::Relaxed);
+    }
+
+    fn is_full(&self, len: usize) -> bool {
+        len > self.cfg.max_entries.unwrap_or(usize::MAX)
+            || self.bytes.load(Ordering::Relaxed) > self.cfg.max_bytes.unwrap_or(usize::MAX)
+    }
+
+    /// Evicts documents (except `keep`) until the store fits into
+    /// `max_entries` and `max_bytes` again.
+    ///
+    /// The eviction callback is called after all locks are released.
+    fn evict(&self, keep: &str) {
+        if !self.is_bounded() || !self.is_full(self.mem.read().len()) {
+            return;
+        }
+        let mut evicted = vec![];
+        let mut meta = self.meta.write();
+        let mut map = self.mem.write();
+        let mut candidates = self.candidates.lock();
+        if candidates.len() > map.len() {
+            // Mostly removed documents.
+            candidates.clear();
+        }
+        let mut kept = None;
+        while self.is_full(map.len()) {
+            let Some((rank, id)) = candidates.pop() else {
+                let ranked = map
+                    .iter()
+                    .filter(|(id, _)| *id != keep)
+                    .map(|(id, e)| (e.usage.rank(self.cfg.eviction), id))
+                    .collect();
+                if !candidates.refill(ranked) {
+                    break;
+                }
+                continue;
+            };
+            let Some(entry) = map.get(&id) else {
+                // Already removed.
+                continue;
+            };
+            if id == keep {
+                kept = Some((rank, id));
+                continue;
+            }
+            let current = entry.usage.rank(self.cfg.eviction);
+            if current != rank {
+                // Used since it was added.
+                candidates.offer(current, id);
+                continue;
+            }
+            if let Some(entry) = map.remove(&id) {
+                self.untrack(&entry);
+                meta.remove(&id);
+                evicted.push((id, entry));
+            }
+        }
+        if let Some((rank, id)) = kept {
+            candidates.offer(rank, id);
+        }
+        drop(candidates);
+        drop(map);
+        drop(meta);
+        let Some(callback) = self.on_evict.get() else {
+            return;
+        };
+        for (id, entry) in evicted {
+            match entry.doc.into_inner().to_value() {
+                Ok(doc) => callback(&id, doc),
+                Err(err) => log::warn!("Evicted undecodable document '{id}': {err}"),
+            }
+        }
+    }
+
+    fn is_expired(&self, id: &str, now: u64) -> bool {
+        self.meta.read().get(id).is_some_and(|m| m.is_expired(now))
+    }
+}
+
+impl Entry {
+    fn new(doc: Doc) -> Self {
+        Self {
+            doc: Mutex::new(doc),
+            size: AtomicUsize::new(0),
+            usage: Usage::default(),
+        }
+    }
+}
+
+impl Doc {
+    fn new<T: Serialize>(obj: &T, parsed: bool) -> Result<Self> {
+        if parsed {
+            serde_json::to_value(obj).map(|v| Self::Parsed(Arc::new(v)))
+        } else {
+            serde_json::to_string(obj).map(Self::Text)
+        }
+        .map_err(|err| Error::new(ErrorKind::Other, err))
+    }
+
+    fn from_value(doc: Value, parsed: bool) -> Result<Self> {
+        if parsed {
+            Ok(Self::Parsed(Arc::new(doc)))
+        } else {
+            Self::new(&doc, false)
+        }
+    }
+
+    fn to_value(&self) -> Result<Value> {
+        match self {
+            Self::Text(json) => {
+                serde_json::from_str(json).map_err(|err| Error::new(ErrorKind::Other, err))
+            }
+            Self::Parsed(doc) => Ok(Value::clone(doc)),
+        }
+    }
+
+    /// The size of the document as JSON text.
+    fn len(&self) -> usize {
+        match self {
+            Self::Text(json) => json.len(),
+            Self::Parsed(doc) => doc.to_string().len(),
+        }
+    }
+}
+
+impl fmt::Display for Doc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Text(json) => f.write_str(json),
+            Self::Parsed(doc) => doc.fmt(f),
+        }
+    }
+}
+
+/// Decodes a document.
+///
+/// Parsed documents are decoded without holding the lock.
+fn decode<T>(doc: &Mutex<Doc>) -> Result<T>
+where
+    for<'de> T: Deserialize<'de>,
+{
+    let guard = doc.lock();
+    match &*guard {
+        Doc::Text(json) => {
+            serde_json::from_str(json).map_err(|err| Error::new(ErrorKind::Other, err))
+        }
+        Doc::Parsed(doc) => {
+            let doc = Arc::clone(doc);
+            drop(guard);
+            T::deserialize(&*doc).map_err(|err| Error::new(ErrorKind::Other, err))
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::evict::Eviction;
     use serde_derive::{Deserialize, Serialize};
-    use std::thread;
+    use std::{
+        thread,
+        time::{SystemTime, UNIX_EPOCH},
+    };
 
     #[derive(Serialize, Deserialize)]
     struct X {
@@ -109,7 +724,7 @@ mod tests {
         let data = X { x: 56 };
         let id = db.save(&data).unwrap();
         assert_eq!(db.mem.read().len(), 1);
-        let json = db.mem.read().get(&id).unwrap().lock().clone();
+        let json = db.mem.read().get(&id).unwrap().doc.lock().to_string();
         assert_eq!(json, "{\"x\":56}");
     }
 
@@ -118,11 +733,11 @@ mod tests {
         let db = MemoryStore::default();
         let mut data = X { x: 56 };
         let id = db.save(&data).unwrap();
-        let json = db.mem.read().get(&id).unwrap().lock().clone();
+        let json = db.mem.read().get(&id).unwrap().doc.lock().to_string();
         assert_eq!(json, "{\"x\":56}");
         data.x += 1;
         db.save_with_id(&data, &id).unwrap();
-        let json = db.mem.read().get(&id).unwrap().lock().clone();
+        let json = db.mem.read().get(&id).unwrap().doc.lock().to_string();
         assert_eq!(json, "{\"x\":57}");
     }
 
@@ -154,7 +769,7 @@ mod tests {
     fn save_empty_obj() {
         let db = MemoryStore::default();
         let id = db.save(&Empty {}).unwrap();
-        let json = db.mem.read().get(&id).unwrap().lock().clone();
+        let json = db.mem.read().get(&id).unwrap().doc.lock().to_string();
         assert_eq!(json, "{}");
     }
 
@@ -163,16 +778,17 @@ mod tests {
         let db = MemoryStore::default();
         let data = Y { y: -7 };
         db.save_with_id(&data, "foo").unwrap();
-        let json = db.mem.read().get("foo").unwrap().lock().clone();
+        let json = db.mem.read().get("foo").unwrap().doc.lock().to_string();
         assert_eq!(json, "{\"y\":-7}");
     }
 
     #[test]
     fn get() {
         let db = MemoryStore::default();
-        db.mem
-            .write()
-            .insert("foo".to_string(), Mutex::new("{\"z\":9.9}".to_string()));
+        db.mem.write().insert(
+            "foo".to_string(),
+            Entry::new(Doc::Text("{\"z\":9.9}".to_string())),
+        );
         let obj: Z = db.get("foo").unwrap();
         assert_eq!(obj.z, 9.9);
     }
@@ -196,11 +812,12 @@ mod tests {
         }
         db.mem.write().insert(
             "foo".to_string(),
-            Mutex::new("{\"x\":1,\"y\":0}".to_string()),
+            Entry::new(Doc::Text("{\"x\":1,\"y\":0}".to_string())),
+        );
+        db.mem.write().insert(
+            "bar".to_string(),
+            Entry::new(Doc::Text("{\"y\":2}".to_string())),
         );
-        db.mem
-            .write()
-            .insert("bar".to_string(), Mutex::new("{\"y\":2}".to_string()));
 
         let all_x: BTreeMap<String, X> = db.all().unwrap();
         let all_y: BTreeMap<String, Y> = db.all().unwrap();
@@ -209,6 +826,287 @@ mod tests {
         assert_eq!(all_y.get("bar").unwrap().y, 2);
     }
 
+    #[test]
+    fn save_with_ttl() {
+        let db = MemoryStore::default();
+        db.save_with_ttl(&X { x: 1 }, "short", Duration::ZERO)
+            .unwrap();
+        db.save_with_ttl(&X { x: 2 }, "long", Duration::from_secs(3600))
+            .unwrap();
+        assert!(db.get::<X>("short").is_err());
+        assert_eq!(db.get::<X>("long").unwrap().x, 2);
+        assert_eq!(db.all::<X>().unwrap().len(), 1);
+        assert_eq!(db.purge_expired().unwrap(), 1);
+        assert_eq!(db.mem.read().len(), 1);
+        db.save_with_id(&X { x: 3 }, "long").unwrap();
+        assert!(db.meta.read().is_empty());
+    }
+
+    #[test]
+    fn meta() {
+        #[derive(Debug)]
+        struct FixedClock;
+
+        impl Clock for FixedClock {
+            fn now(&self) -> SystemTime {
+                UNIX_EPOCH + Duration::from_secs(42)
+            }
+        }
+
+        let mut cfg = Config::default();
+        cfg.metadata = true;
+        let db = MemoryStore::new(cfg, Arc::new(FixedClock));
+        db.save_with_id(&X { x: 1 }, "foo").unwrap();
+        db.save_with_id(&X { x: 10 }, "foo").unwrap();
+        let meta = db.meta("foo").unwrap();
+        assert_eq!(meta.created_at, Some(UNIX_EPOCH + Duration::from_secs(42)));
+        assert_eq!(meta.updated_at, meta.created_at);
+        assert_eq!(meta.revision, 2);
+        assert_eq!(meta.size, 8);
+        db.delete("foo").unwrap();
+        assert!(db.meta("foo").is_err());
+        assert!(db.meta.read().is_empty());
+    }
+
+    #[test]
+    fn history() {
+        let mut cfg = Config::default();
+        cfg.history = Some(Retention::default());
+        let db = MemoryStore::new(cfg, Arc::new(SystemClock));
+        db.save_with_id(&X { x: 1 }, "foo").unwrap();
+        db.save_with_id(&X { x: 2 }, "foo").unwrap();
+        db.delete("foo").unwrap();
+        let versions = db.history("foo").unwrap();
+        assert_eq!(versions.len(), 2);
+        assert_eq!(db.get_at::<X>("foo", 1).unwrap().x, 1);
+        db.revert("foo", 2).unwrap();
+        assert_eq!(db.get::<X>("foo").unwrap().x, 2);
+        assert!(db.get_at::<X>("foo", 3).is_err());
+    }
+
+    #[test]
+    fn keys() {
+        let db = MemoryStore::default();
+        assert!(db.is_empty().unwrap());
+        db.save_with_id(&X { x: 1 }, "b").unwrap();
+        db.save_with_id(&X { x: 2 }, "a").unwrap();
+        db.save_with_ttl(&X { x: 3 }, "c", Duration::ZERO).unwrap();
+        assert_eq!(db.keys().unwrap(), vec!["a", "b"]);
+        assert_eq!(db.count().unwrap(), 2);
+        assert!(!db.is_empty().unwrap());
+        assert!(db.exists("a").unwrap());
+        assert!(!db.exists("c").unwrap());
+    }
+
+    #[test]
+    fn scan() {
+        let db = MemoryStore::default();
+        for (x, id) in (0..).zip(["b", "a", "ab", "c"]) {
+            db.save_with_id(&X { x }, id).unwrap();
+        }
+        let opts = ScanOptions {
+            limit: Some(2),
+            reverse: false,
+        };
+        let res = db.scan::<X>(&|id| id < "c", opts).unwrap();
+        assert_eq!(res.len(), 2);
+        assert_eq!(res[0].0, "a");
+        assert_eq!(res[1].0, "ab");
+        assert_eq!(res[1].1.x, 2);
+    }
+
+    #[test]
+    fn paths() {
+        let mut cfg = Config::default();
+        cfg.metadata = true;
+        cfg.history = Some(Retention::default());
+        let db = MemoryStore::new(cfg, Arc::new(SystemClock));
+        db.save_with_ttl(&X { x: 1 }, "foo", Duration::from_secs(3600))
+            .unwrap();
+        db.set_path("foo", "/x", &2).unwrap();
+        assert_eq!(db.get_path::<u32>("foo", "/x").unwrap(), 2);
+        assert_eq!(db.get::<X>("foo").unwrap().x, 2);
+        assert_eq!(db.get_at::<X>("foo", 1).unwrap().x, 1);
+        let meta = db.meta("foo").unwrap();
+        assert_eq!(meta.revision, 2);
+        assert!(meta.expires_at.is_some());
+        assert!(db.set_path("bar", "/x", &2).is_err());
+    }
+
+    #[test]
+    fn parsed() {
+        let mut cfg = Config::default();
+        cfg.parsed = true;
+        cfg.metadata = true;
+        cfg.history = Some(Retention::default());
+        let db = MemoryStore::new(cfg, Arc::new(SystemClock));
+        db.save_with_id(&X { x: 1 }, "foo").unwrap();
+        assert!(matches!(*db.mem.read()["foo"].doc.lock(), Doc::Parsed(_)));
+        assert_eq!(db.get::<X>("foo").unwrap().x, 1);
+        db.set_path("foo", "/x", &2).unwrap();
+        assert_eq!(db.all::<X>().unwrap()["foo"].x, 2);
+        assert_eq!(db.get_at::<X>("foo", 1).unwrap().x, 1);
+        assert_eq!(db.meta("foo").unwrap().size, 7);
+        assert!(db.get::<Y>("foo").is_err());
+        assert_eq!(db.verify::<Y>().unwrap().issues.len(), 1);
+    }
+
+    #[test]
+    fn evict_lru() {
+        let mut cfg = Config::default();
+        cfg.max_entries = Some(2);
+        cfg.metadata = true;
+        let db = MemoryStore::new(cfg, Arc::new(SystemClock));
+        let evicted = Arc::new(Mutex::new(vec![]));
+        let e = Arc::clone(&evicted);
+        db.on_evict().set(Arc::new(move |id: &str, doc| {
+            e.lock().push((id.to_owned(), doc));
+        }));
+        db.save_with_id(&X { x: 1 }, "a").unwrap();
+        db.save_with_id(&X { x: 2 }, "b").unwrap();
+        db.get::<X>("a").unwrap();
+        db.save_with_id(&X { x: 3 }, "c").unwrap();
+        assert_eq!(db.keys().unwrap(), ["a", "c"]);
+        assert!(db.meta("b").is_err());
+        assert_eq!(
+            *evicted.lock(),
+            [("b".to_owned(), serde_json::json!({ "x": 2 }))]
+        );
+    }
+
+    #[test]
+    fn evict_lfu() {
+        let mut cfg = Config::default();
+        cfg.max_entries = Some(2);
+        cfg.eviction = Eviction::Lfu;
+        let db = MemoryStore::new(cfg, Arc::new(SystemClock));
+        db.save_with_id(&X { x: 1 }, "a").unwrap();
+        db.save_with_id(&X { x: 2 }, "b").unwrap();
+        db.get::<X>("a").unwrap();
+        db.get::<X>("a").unwrap();
+        db.get::<X>("b").unwrap();
+        db.save_with_id(&X { x: 3 }, "c").unwrap();
+        assert_eq!(db.keys().unwrap(), ["a", "c"]);
+        db.save_with_id(&X { x: 4 }, "d").unwrap();
+        assert_eq!(db.keys().unwrap(), ["a", "d"]);
+    }
+
+    #[test]
+    fn evict_max_bytes() {
+        let mut cfg = Config::default();
+        cfg.max_bytes = Some(20);
+        cfg.parsed = true;
+        let db = MemoryStore::new(cfg, Arc::new(SystemClock));
+        db.save_with_id(&X { x: 1 }, "a").unwrap();
+        db.save_with_id(&X { x: 2 }, "b").unwrap();
+        assert_eq!(db.count().unwrap(), 2);
+        db.set_path("b", "/y", &1000).unwrap();
+        assert_eq!(db.keys().unwrap(), ["b"]);
+        db.save_with_id(&Y { y: 123_456_789 }, "c").unwrap();
+        assert_eq!(db.keys().unwrap(), ["c"]);
+    }
+
+    #[test]
+    fn evict_many() {
+        let mut cfg = Config::default();
+        cfg.max_entries = Some(16);
+        cfg.max_bytes = Some(1000);
+        let db = MemoryStore::new(cfg, Arc::new(SystemClock));
+        for i in 0..16 {
+            db.save_with_id(&X { x: i }, &format!("{i:02}")).unwrap();
+        }
+        for i in 0..8 {
+            db.get::<X>(&format!("{i:02}")).unwrap();
+        }
+        for i in 16..24 {
+            db.save_with_id(&X { x: i }, &format!("{i:02}")).unwrap();
+        }
+        let mut keys = db.keys().unwrap();
+        keys.sort();
+        let expected = (0..8).chain(16..24).map(|i| format!("{i:02}"));
+        assert_eq!(keys, expected.collect::<Vec<_>>());
+        assert_eq!(
+            db.bytes.load(Ordering::Relaxed),
+            16 * r#"{"x":0}"#.len() + 8
+        );
+        db.clear().unwrap();
+        assert_eq!(db.bytes.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn bulk() {
+        let db = MemoryStore::default();
+        let res = db
+            .save_many(&[("a", X { x: 1 }), ("b", X { x: 2 })])
+            .unwrap();
+        assert!(res.iter().all(Result::is_ok));
+        let res = db.get_many::<X>(&["b", "c"]).unwrap();
+        assert_eq!(res[0].as_ref().unwrap().x, 2);
+        assert!(res[1].is_err());
+        let res = db.delete_many(&["a", "c"]).unwrap();
+        assert!(res[0].is_ok());
+        assert!(res[1].is_err());
+        assert_eq!(db.clear().unwrap(), 1);
+        assert!(db.all::<X>().unwrap().is_empty());
+    }
+
+    #[test]
+    fn trash() {
+        let mut cfg = Config::default();
+        cfg.trash = true;
+        let db = MemoryStore::new(cfg, Arc::new(SystemClock));
+        db.save_with_id(&X { x: 1 }, "foo").unwrap();
+        db.delete("foo").unwrap();
+        assert!(db.all::<X>().unwrap().is_empty());
+        assert_eq!(db.list_trash().unwrap()[0].id, "foo");
+        db.save_with_id(&X { x: 2 }, "foo").unwrap();
+        assert!(db.restore("foo").is_err());
+        assert_eq!(db.list_trash().unwrap().len(), 1);
+        db.mem.write().clear();
+        db.restore("foo").unwrap();
+        assert_eq!(db.get::<X>("foo").unwrap().x, 1);
+        db.delete("foo").unwrap();
+        assert_eq!(db.empty_trash(Duration::ZERO).unwrap(), 1);
+        assert!(db.list_trash().unwrap().is_empty());
+
+        db.save_with_id(&X { x: 3 }, "k").unwrap();
+        db.delete("k").unwrap();
+        db.save_with_ttl(&X { x: 4 }, "k", Duration::ZERO).unwrap();
+        db.restore("k").unwrap();
+        assert_eq!(db.get::<X>("k").unwrap().x, 3);
+    }
+
+    #[test]
+    fn all_strict() {
+        let db = MemoryStore::default();
+        db.save_with_id(&X { x: 1 }, "x").unwrap();
+        assert_eq!(db.all_strict::<X>().unwrap().len(), 1);
+        db.save_with_id(&Y { y: -1 }, "y").unwrap();
+        assert_eq!(db.all::<X>().unwrap().len(), 1);
+        assert!(db.all_strict::<X>().is_err());
+        let (all, errors) = db.all_with_errors::<X>().unwrap();
+        assert_eq!(all.len(), 1);
+        assert_eq!(errors[0].0, "y");
+    }
+
+    #[test]
+    fn verify_and_repair() {
+        let mut cfg = Config::default();
+        cfg.metadata = true;
+        let db = MemoryStore::new(cfg, Arc::new(SystemClock));
+        db.save_with_id(&X { x: 1 }, "x").unwrap();
+        db.save_with_ttl(&Y { y: -1 }, "y", Duration::ZERO).unwrap();
+        let report = db.verify::<X>().unwrap();
+        assert_eq!(report.issues.len(), 1);
+        assert!(matches!(&report.issues[0], Issue::Undecodable { id, .. } if id == "y"));
+        db.repair::<X>(RepairPolicy::Quarantine).unwrap();
+        assert!(db.verify::<X>().unwrap().is_ok());
+        assert_eq!(db.mem.read().len(), 1);
+        assert!(!db.meta.read().contains_key("y"));
+        assert_eq!(db.purge_expired().unwrap(), 0);
+        assert!(db.meta("x").is_ok());
+    }
+
     #[test]
     fn delete() {
         let db = MemoryStore::default();
@@ -0,0 +1,122 @@
+use std::{
+    fmt::Debug,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// A source of the current time.
+///
+/// The store asks its clock whenever it needs a timestamp, e.g. for
+/// document metadata or expiration. Provide your own implementation to
+/// get deterministic timestamps in tests.
+pub trait Clock: Debug + Send + Sync {
+    fn now(&self) -> SystemTime;
+}
+
+/// The default `Clock` that returns the system time.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// Metadata of a stored document (see `Store::meta`).
+///
+/// The timestamps and the revision are only recorded if
+/// `Config::metadata` is enabled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Metadata {
+    /// The time the document was first saved.
+    pub created_at: Option<SystemTime>,
+    /// The time the document was last saved.
+    pub updated_at: Option<SystemTime>,
+    /// The size of the stored document in bytes.
+    pub size: u64,
+    /// The number of times the document has been saved.
+    pub revision: u64,
+    /// The time the document expires (see `Store::save_with_ttl`).
+    pub expires_at: Option<SystemTime>,
+}
+
+/// Bookkeeping data that is stored alongside a document.
+///
+/// All timestamps are milliseconds since the UNIX epoch.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Meta {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<u64>,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub revision: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<u64>,
+}
+
+impl Meta {
+    /// Returns the metadata of a document that is saved at `now`.
+    ///
+    /// If neither metadata tracking nor a `ttl` is requested,
+    /// there is nothing to store and `None` is returned.
+    pub fn next(prev: Option<&Self>, now: u64, track: bool, ttl: Option<Duration>) -> Option<Self> {
+        let expires_at = ttl.map(|ttl| now.saturating_add(to_millis(ttl)));
+        if !track {
+            return expires_at.map(|_| Self {
+                expires_at,
+                ..Self::default()
+            });
+        }
+        Some(Self {
+            created_at: prev.and_then(|m| m.created_at).or(Some(now)),
+            updated_at: Some(now),
+            revision: prev.map_or(0, |m| m.revision) + 1,
+            expires_at,
+        })
+    }
+
+    /// Returns the remaining time-to-live at `now`, if the document expires.
+    pub fn ttl(&self, now: u64) -> Option<Duration> {
+        self.expires_at
+            .map(|t| Duration::from_millis(t.saturating_sub(now)))
+    }
+
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires_at.is_some_and(|t| t <= now)
+    }
+
+    pub fn to_metadata(&self, size: u64) -> Metadata {
+        Metadata {
+            created_at: self.created_at.map(from_millis),
+            updated_at: self.updated_at.map(from_millis),
+            size,
+            revision: self.revision,
+            expires_at: self.expires_at.map(from_millis),
+        }
+    }
+}
+
+/// Returns the current time of the `clock` in milliseconds since the UNIX epoch.
+pub fn now_millis(clock: &dyn Clock) -> u64 {
+    clock
+        .now()
+        .duration_since(UNIX_EPOCH)
+        .map(to_millis)
+        .unwrap_or_default()
+}
+
+fn to_millis(d: Duration) -> u64 {
+    u64::try_from(d.as_millis()).unwrap_or(u64::MAX)
+}
+
+fn from_millis(ms: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_millis(ms)
+}
+
+#[allow(clippy::trivially_copy_pass_by_ref)]
+const fn is_zero(x: &u64) -> bool {
+    *x == 0
+}
@@ -0,0 +1,72 @@
+use std::io::{Error, ErrorKind, Result};
+
+use serde_json::Value;
+
+/// Returns the value at a JSON Pointer (RFC 6901).
+pub fn get<'a>(doc: &'a Value, pointer: &str) -> Result<&'a Value> {
+    check(pointer)?;
+    doc.pointer(pointer).ok_or_else(|| not_found(pointer))
+}
+
+/// Replaces the value at a JSON Pointer (RFC 6901).
+///
+/// Like the `add` operation of JSON Patch, the last reference token may
+/// also name a new object member or `-` to append to an array,
+/// but all parents must exist.
+pub fn set(doc: &mut Value, pointer: &str, value: Value) -> Result<()> {
+    check(pointer)?;
+    if let Some(target) = doc.pointer_mut(pointer) {
+        *target = value;
+        return Ok(());
+    }
+    let (parent, token) = pointer.rsplit_once('/').ok_or_else(|| not_found(pointer))?;
+    match doc.pointer_mut(parent) {
+        Some(Value::Object(object)) => {
+            object.insert(token.replace("~1", "/").replace("~0", "~"), value);
+            Ok(())
+        }
+        Some(Value::Array(array)) if token == "-" || token == array.len().to_string() => {
+            array.push(value);
+            Ok(())
+        }
+        _ => Err(not_found(pointer)),
+    }
+}
+
+fn check(pointer: &str) -> Result<()> {
+    if pointer.is_empty() || pointer.starts_with('/') {
+        Ok(())
+    } else {
+        Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("invalid JSON pointer '{pointer}'"),
+        ))
+    }
+}
+
+fn not_found(pointer: &str) -> Error {
+    Error::new(ErrorKind::NotFound, format!("no such path '{pointer}'"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn get_and_set() {
+        let mut doc = json!({ "a": { "b/c": [1] } });
+        assert_eq!(get(&doc, "/a/b~1c/0").unwrap(), 1);
+        assert_eq!(get(&doc, "/x").unwrap_err().kind(), ErrorKind::NotFound);
+        assert_eq!(get(&doc, "a").unwrap_err().kind(), ErrorKind::InvalidInput);
+
+        set(&mut doc, "/a/b~1c/0", json!(2)).unwrap();
+        set(&mut doc, "/a/b~1c/-", json!(3)).unwrap();
+        set(&mut doc, "/a/d~0e", json!(true)).unwrap();
+        assert_eq!(doc, json!({ "a": { "b/c": [2, 3], "d~e": true } }));
+        let err = set(&mut doc, "/x/y", json!(0)).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+        set(&mut doc, "", json!(null)).unwrap();
+        assert_eq!(doc, Value::Null);
+    }
+}
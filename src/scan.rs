@@ -0,0 +1,32 @@
+/// Options for ordered scans over document IDs
+/// (see `Store::scan_prefix` and `Store::range`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ScanOptions {
+    /// Return at most this many documents.
+    pub limit: Option<usize>,
+    /// Return the documents in descending ID order.
+    pub reverse: bool,
+}
+
+/// One page of documents (see `Store::page`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Page<T> {
+    /// The documents of this page, ordered by ID.
+    pub items: Vec<(String, T)>,
+    /// The cursor of the next page or `None` if this is the last one.
+    pub next: Option<String>,
+}
+
+/// Returns the IDs that match `filter` in scan order.
+pub fn select<'a>(
+    ids: impl IntoIterator<Item = &'a str>,
+    filter: &dyn Fn(&str) -> bool,
+    opts: ScanOptions,
+) -> Vec<&'a str> {
+    let mut ids = ids.into_iter().filter(|id| filter(id)).collect::<Vec<_>>();
+    ids.sort_unstable();
+    if opts.reverse {
+        ids.reverse();
+    }
+    ids
+}
@@ -0,0 +1,124 @@
+use std::io::Result;
+#[cfg(feature = "jsonschema")]
+use std::{
+    fmt,
+    io::{Error, ErrorKind},
+    sync::Arc,
+};
+
+#[cfg(feature = "jsonschema")]
+use parking_lot::RwLock;
+use serde::Serialize;
+#[cfg(feature = "jsonschema")]
+use serde_json::Value;
+
+/// The JSON Schema that documents are validated against before they're written.
+///
+/// Without the `jsonschema` feature every document is accepted.
+#[derive(Debug, Clone, Default)]
+pub struct Schema {
+    #[cfg(feature = "jsonschema")]
+    validator: Arc<RwLock<Option<Arc<jsonschema::Validator>>>>,
+}
+
+#[cfg(feature = "jsonschema")]
+impl Schema {
+    /// Replaces (or removes if `schema` is `None`) the schema.
+    pub fn set(&self, schema: Option<&Value>) -> Result<()> {
+        let validator = schema
+            .map(|schema| {
+                jsonschema::validator_for(schema)
+                    .map_err(|err| Error::new(ErrorKind::InvalidInput, err.to_string()))
+            })
+            .transpose()?;
+        *self.validator.write() = validator.map(Arc::new);
+        Ok(())
+    }
+
+    /// Fails with an `ErrorKind::InvalidData` error that wraps a
+    /// `ValidationError` if `doc` doesn't conform to the schema.
+    pub fn check<T: Serialize>(&self, doc: &T) -> Result<()> {
+        if self.validator.read().is_none() {
+            return Ok(());
+        }
+        let doc = serde_json::to_value(doc).map_err(|err| Error::new(ErrorKind::Other, err))?;
+        self.validate(&doc)
+            .map_err(|err| Error::new(ErrorKind::InvalidData, err))
+    }
+
+    pub fn validate(&self, doc: &Value) -> std::result::Result<(), ValidationError> {
+        let Some(validator) = self.validator.read().clone() else {
+            return Ok(());
+        };
+        let violations = validator
+            .iter_errors(doc)
+            .map(|err| Violation {
+                path: err.instance_path().to_string(),
+                message: err.to_string(),
+            })
+            .collect::<Vec<_>>();
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(ValidationError { violations })
+        }
+    }
+}
+
+#[cfg(not(feature = "jsonschema"))]
+impl Schema {
+    #[allow(clippy::unused_self, clippy::unnecessary_wraps)]
+    pub fn check<T: Serialize>(&self, _: &T) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// A document that doesn't conform to the schema of a store
+/// (see `Store::set_schema`).
+///
+/// Writes of such documents fail with an `io::Error` of kind
+/// `ErrorKind::InvalidData` that wraps this error:
+///
+/// ```rust,no_run
+/// # let db = jfs::Store::new("data").unwrap();
+/// if let Err(err) = db.save(&serde_json::json!({ "name": 42 })) {
+///     if let Some(err) = err.get_ref().and_then(|e| e.downcast_ref::<jfs::ValidationError>()) {
+///         for violation in &err.violations {
+///             println!("{}: {}", violation.path, violation.message);
+///         }
+///     }
+/// }
+/// ```
+#[cfg(feature = "jsonschema")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationError {
+    pub violations: Vec<Violation>,
+}
+
+/// A single schema violation of a document.
+#[cfg(feature = "jsonschema")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    /// JSON Pointer to the offending part of the document.
+    pub path: String,
+    pub message: String,
+}
+
+#[cfg(feature = "jsonschema")]
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "document doesn't match the schema")?;
+        for (i, v) in self.violations.iter().enumerate() {
+            let sep = if i == 0 { ": " } else { ", " };
+            if v.path.is_empty() {
+                write!(f, "{sep}{}", v.message)?;
+            } else {
+                write!(f, "{sep}{} ({})", v.message, v.path)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "jsonschema")]
+impl std::error::Error for ValidationError {}
@@ -0,0 +1,55 @@
+use std::{
+    sync::Arc,
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use parking_lot::{Condvar, Mutex, MutexGuard};
+
+/// Handle of a background thread that periodically runs a task, such as
+/// removing expired documents (see `Store::spawn_sweeper`).
+///
+/// The thread is stopped when the handle is dropped.
+#[derive(Debug)]
+pub struct Sweeper {
+    stop: Arc<(Mutex<bool>, Condvar)>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Sweeper {
+    pub(crate) fn spawn<F>(interval: Duration, mut f: F) -> Self
+    where
+        F: FnMut() + Send + 'static,
+    {
+        let stop = Arc::new((Mutex::new(false), Condvar::new()));
+        let s = Arc::clone(&stop);
+        let thread = thread::spawn(move || {
+            let (stopped, cond) = &*s;
+            let mut stopped = stopped.lock();
+            loop {
+                if !*stopped {
+                    cond.wait_for(&mut stopped, interval);
+                }
+                if *stopped {
+                    break;
+                }
+                MutexGuard::unlocked(&mut stopped, &mut f);
+            }
+        });
+        Self {
+            stop,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for Sweeper {
+    fn drop(&mut self) {
+        let (stopped, cond) = &*self.stop;
+        *stopped.lock() = true;
+        cond.notify_all();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,35 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// A soft-deleted document (see `Store::list_trash`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrashEntry {
+    pub id: String,
+    /// The time the document was deleted.
+    pub deleted_at: SystemTime,
+}
+
+/// A soft-deleted document as it is stored.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Trashed {
+    /// Milliseconds since the UNIX epoch.
+    pub deleted_at: u64,
+    pub doc: Value,
+}
+
+impl Trashed {
+    pub fn to_entry(&self, id: &str) -> TrashEntry {
+        TrashEntry {
+            id: id.to_owned(),
+            deleted_at: UNIX_EPOCH + Duration::from_millis(self.deleted_at),
+        }
+    }
+
+    /// Returns `true` if the document was deleted at least `older_than` before `now`.
+    pub fn is_older_than(&self, older_than: Duration, now: u64) -> bool {
+        let age = now.saturating_sub(self.deleted_at);
+        u128::from(age) >= older_than.as_millis()
+    }
+}
@@ -0,0 +1,63 @@
+use std::{fmt, io::Error, path::PathBuf};
+
+/// A problem found by [`Store::verify`](crate::Store::verify).
+#[derive(Debug)]
+pub enum Issue {
+    /// A file that could not be read.
+    Unreadable { path: PathBuf, error: Error },
+    /// A file that does not contain valid JSON.
+    Malformed { path: PathBuf, error: Error },
+    /// A single-file store whose content is not a JSON object.
+    NotAnObject { path: PathBuf },
+    /// A temporary file left behind by an interrupted write.
+    TmpFile { path: PathBuf },
+    /// A file whose name can't be mapped back to a document ID.
+    InvalidId { path: PathBuf },
+    /// A document that can't be decoded as the requested type.
+    Undecodable { id: String, error: Error },
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unreadable { path, error } => {
+                write!(f, "{}: unreadable: {error}", path.display())
+            }
+            Self::Malformed { path, error } => {
+                write!(f, "{}: malformed JSON: {error}", path.display())
+            }
+            Self::NotAnObject { path } => write!(f, "{}: not a JSON object", path.display()),
+            Self::TmpFile { path } => write!(f, "{}: stale temporary file", path.display()),
+            Self::InvalidId { path } => write!(f, "{}: invalid document ID", path.display()),
+            Self::Undecodable { id, error } => write!(f, "{id}: can't be decoded: {error}"),
+        }
+    }
+}
+
+/// The result of an integrity check.
+#[derive(Debug, Default)]
+pub struct Report {
+    pub issues: Vec<Issue>,
+}
+
+impl Report {
+    /// Returns `true` if no issues were found.
+    #[must_use]
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+/// Defines what [`Store::repair`](crate::Store::repair) does with bad entries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RepairPolicy {
+    /// Move bad entries into a quarantine area next to the store.
+    ///
+    /// In directory mode this is the `.quarantine` subdirectory,
+    /// in single-file mode it's a `<name>.quarantine` directory
+    /// next to the JSON file. There, the bad documents of each repair are
+    /// written to one file that maps their IDs to the documents.
+    Quarantine,
+    /// Delete bad entries.
+    Remove,
+}
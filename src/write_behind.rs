@@ -0,0 +1,181 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    io::Result,
+    path::PathBuf,
+    sync::Arc,
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use parking_lot::{Condvar, Mutex};
+use serde_json::Value;
+
+/// Defines when buffered documents of a single-file store are written
+/// if write-behind mode is enabled (see `Config::write_behind`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WriteBehind {
+    /// Write the buffered documents once no document was saved for this long.
+    pub delay: Duration,
+    /// Write the buffered documents as soon as this many are pending.
+    pub max_pending: usize,
+}
+
+impl Default for WriteBehind {
+    fn default() -> Self {
+        Self {
+            delay: Duration::from_secs(1),
+            max_pending: 1000,
+        }
+    }
+}
+
+type Flush = Box<dyn Fn(&BTreeMap<String, Value>) -> Result<()> + Send + Sync>;
+type Recover = Box<dyn Fn(&BTreeMap<String, Value>) -> Result<PathBuf> + Send + Sync>;
+
+/// Saved documents that are not written yet.
+///
+/// A background thread writes them after `WriteBehind::delay`;
+/// the rest is written when the buffer is dropped. If that fails,
+/// they are handed to the `recover` callback instead.
+pub struct Buffer {
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<()>>,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    cond: Condvar,
+    cfg: WriteBehind,
+    flush: Flush,
+    recover: Recover,
+}
+
+struct State {
+    pending: BTreeMap<String, Value>,
+    last_write: Instant,
+    stopped: bool,
+}
+
+impl Buffer {
+    pub fn spawn<F, R>(cfg: WriteBehind, flush: F, recover: R) -> Self
+    where
+        F: Fn(&BTreeMap<String, Value>) -> Result<()> + Send + Sync + 'static,
+        R: Fn(&BTreeMap<String, Value>) -> Result<PathBuf> + Send + Sync + 'static,
+    {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State {
+                pending: BTreeMap::new(),
+                last_write: Instant::now(),
+                stopped: false,
+            }),
+            cond: Condvar::new(),
+            cfg,
+            flush: Box::new(flush),
+            recover: Box::new(recover),
+        });
+        let s = Arc::clone(&shared);
+        let thread = thread::spawn(move || s.run());
+        Self {
+            shared,
+            thread: Some(thread),
+        }
+    }
+
+    /// Buffers a document and writes all pending ones if there
+    /// are `WriteBehind::max_pending` of them.
+    ///
+    /// The document is buffered even if writing fails; like in the
+    /// background, the error is only logged and writing is retried later.
+    pub fn push(&self, id: &str, doc: Value) {
+        let mut state = self.shared.state.lock();
+        state.pending.insert(id.to_owned(), doc);
+        state.last_write = Instant::now();
+        if state.pending.len() >= self.shared.cfg.max_pending {
+            if let Err(err) = self.shared.flush(&mut state) {
+                log::error!(
+                    "Failed to write {} buffered documents: {err}",
+                    state.pending.len()
+                );
+            }
+        }
+        self.shared.cond.notify_one();
+    }
+
+    pub fn get(&self, id: &str) -> Option<Value> {
+        self.shared.state.lock().pending.get(id).cloned()
+    }
+
+    /// Writes all pending documents.
+    ///
+    /// If that fails, they stay pending and are retried later.
+    pub fn flush(&self) -> Result<()> {
+        self.shared.flush(&mut self.shared.state.lock())
+    }
+}
+
+impl Shared {
+    fn flush(&self, state: &mut State) -> Result<()> {
+        if state.pending.is_empty() {
+            return Ok(());
+        }
+        (self.flush)(&state.pending)?;
+        state.pending.clear();
+        Ok(())
+    }
+
+    fn run(&self) {
+        let mut state = self.state.lock();
+        while !state.stopped {
+            if state.pending.is_empty() {
+                self.cond.wait(&mut state);
+                continue;
+            }
+            let deadline = state.last_write + self.cfg.delay;
+            if Instant::now() < deadline {
+                self.cond.wait_until(&mut state, deadline);
+                continue;
+            }
+            if let Err(err) = self.flush(&mut state) {
+                log::error!(
+                    "Failed to write {} buffered documents: {err}",
+                    state.pending.len()
+                );
+                // Retry after another delay.
+                state.last_write = Instant::now();
+            }
+        }
+    }
+}
+
+impl Drop for Buffer {
+    fn drop(&mut self) {
+        self.shared.state.lock().stopped = true;
+        self.shared.cond.notify_all();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        if let Err(err) = self.flush() {
+            let state = self.shared.state.lock();
+            let count = state.pending.len();
+            match (self.shared.recover)(&state.pending) {
+                Ok(path) => log::error!(
+                    "Failed to write {count} buffered documents: {err}; saved them to {}",
+                    path.display()
+                ),
+                Err(recover_err) => {
+                    log::error!("Lost {count} buffered documents: {err}; {recover_err}");
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Buffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Buffer")
+            .field("cfg", &self.shared.cfg)
+            .field("pending", &self.shared.state.lock().pending.len())
+            .finish_non_exhaustive()
+    }
+}